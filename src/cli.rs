@@ -1,375 +1,1067 @@
-use crate::database;
-use crate::generation_logic::GenerationData;
-use dotenv;
+//! Command line interface. `generate` is the default command, so `password_generator --minlength
+//! 16` works without naming it; profiles are managed with the `profile` subcommands.
+use clap::builder::RangedU64ValueParser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use password_generator::{database, migrations};
+use password_generator::database::SettingColumn;
+use password_generator::{Error, GenerationData};
+use password_generator::pattern::Pattern;
+use password_generator::pin;
+use password_generator::pin::PinRules;
+use password_generator::token::TokenFormat;
+use password_generator::derive::DerivationAlgorithm;
+use password_generator::generation_logic;
+use password_generator::generation_logic::{Language, Separator, WordStyle};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::env;
-use std::process::exit;
-#[derive(PartialEq, Debug)]
-pub enum ArgType {
-    MinimumLength,
-    IncludeNumbers,
-    IncludeSpecial,
-    IncludeUcase,
-    UseWords,
-    Overwrite,
-    Profile,
-    Error,
-    ListProfiles,
-    ProfileInfo,
-    Help,
-    NewProfile,
-}
-#[derive(PartialEq, Debug)]
-pub enum ArgValue {
-    Bool(bool),
-    Int(usize),
-    String(String),
-    NoValue,
-    Error,
-}
-#[derive(PartialEq, Debug)]
-pub enum Argument {
-    ParsedArgument(ArgType, ArgValue),
-    Error
-}
-pub fn is_arg(input: &str) -> bool{
-    match input {
-        "--minlength" => {
-            true
-        },
-        "--include_nums" => {
-            true
-        },
-        "--include_spec" => {
-            true
-        },
-        "--include_ucase" => {
-            true
-        },
-        "--use_dict_words" => {
-            true
-        },
-        "--overwrite" => {
-            true
-        }
-        "--profile" => {
-            true
-        }
-        "--help" => {
-            true
-        }
-        "--new_profile" => {
-            true
-        }
-        "--list_profiles" => {
-            true
-        }
-        "--profile_info" => {
-            true
-        }
-        _ => {
-            false
-        },
-    }
-}
-pub fn parse_single_arg(arg_type: &str, arg_value: &str) -> Argument{
-    let parsed_arg_value = match arg_value {
-        "true" => ArgValue::Bool(true),
-        "false" => ArgValue::Bool(false),
-        "" => ArgValue::NoValue,
-        x => {
-            if let Ok(parsed_usize) = x.parse::<usize>() {
-                ArgValue::Int(parsed_usize)
-            }
-            else{
-                ArgValue::String(String::from(x))
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use crate::output;
+use crate::output::OutputFormat;
+
+const EXIT_CODES: &str = "Exit codes:
+  0  success
+  2  invalid command line arguments
+  3  database error, including a failed backup or migration
+  4  profile not found
+  5  generation settings cannot be satisfied
+  6  profile already exists
+  7  a file or stdin could not be read or written
+  8  a confirmation prompt was declined";
+
+/// A command line tool to generate random passwords, passphrases, PINs and tokens, with profiles
+/// that save the settings used
+#[derive(Parser, Debug)]
+#[command(name = "password_generator", after_help = EXIT_CODES)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+    /// Print only the output, without the status messages on stderr
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Profile database to use, instead of the one found through PASSWORD_GENERATOR_DB, the config
+    /// file or $XDG_DATA_HOME
+    #[arg(long, global = true, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+}
+impl Cli {
+    /// Parses the command line. Generation options given before a subcommand are refused, since
+    /// they would be silently ignored, while the global options work on either side of it.
+    pub fn try_parse_args<I, T>(args: I) -> Result<Cli, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Cli::command();
+        let matches = command.try_get_matches_from_mut(args)?;
+        if let Some(subcommand) = matches.subcommand_name() {
+            let misplaced = command.get_arguments()
+                .filter(|arg| !arg.is_global_set())
+                .find(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine));
+            if let Some(arg) = misplaced {
+                let name = arg.get_long().map(|long| format!("--{}", long)).unwrap_or_else(|| arg.get_id().to_string());
+                return Err(command.error(ErrorKind::ArgumentConflict,
+                                         format!("'{}' can't be used before the '{}' command", name, subcommand)));
             }
         }
-    };
-    let parsed_arg_type = match arg_type {
-    //TODO: check for "--help" arg, and set up some documentation for options available
-        "--minlength" => {
-            ArgType::MinimumLength
-        },
-        "--include_nums" => {
-            ArgType::IncludeNumbers
-        },
-        "--include_spec" => {
-            ArgType::IncludeSpecial
-        },
-        "--include_ucase" => {
-            ArgType::IncludeUcase
-        },
-        "--use_dict_words" => {
-            ArgType::UseWords
-        },
-        "--overwrite" => {
-            ArgType::Overwrite
-        }
-        "--profile" => {
-            //TODO: check if we actually use this anywhere;
-            ArgType::Profile
-        }
-        "--help" => {
-            ArgType::Help
-        }
-        "--new_profile" => {
-            ArgType::NewProfile
-        }
-        "--list_profiles" => {
-            ArgType::ListProfiles
-        }
-        "--profile_info" => {
-            ArgType::ProfileInfo
-        }
-        _ => {
-            ArgType::Error
-        },
-    };
-    if parsed_arg_type != ArgType::Error && parsed_arg_value != ArgValue::Error {
-        Argument::ParsedArgument(parsed_arg_type, parsed_arg_value)
+        Cli::from_arg_matches(&matches).map_err(|x| x.format(&mut command))
+    }
+}
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate passwords (the default when no command is given)
+    Generate(GenerateArgs),
+    /// Manage saved profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Check that settings or a profile can generate passwords, and show their entropy
+    Check(CheckArgs),
+    /// Write saved profiles as JSON
+    Export(ExportArgs),
+    /// Save the profiles from a JSON file written by `export`
+    Import(ImportArgs),
+    /// Maintain the profile database
+    #[command(subcommand)]
+    Db(DbCommand),
+}
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Bring the database schema up to date, backing up the file first
+    Migrate {
+        /// Only list the pending migrations
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+#[derive(Subcommand, Debug)]
+pub enum ProfileCommand {
+    /// Save a new profile with the given settings
+    Create {
+        name: String,
+        #[command(flatten)]
+        settings: Box<SettingsArgs>,
+    },
+    /// Change the given settings of a profile, leaving the others as they are
+    Update {
+        name: String,
+        #[command(flatten)]
+        settings: Box<SettingsArgs>,
+        /// Put a setting back to its default, e.g. `--unset pin_length`; can be repeated
+        #[arg(long, value_name = "SETTING", value_parser = parse_setting_column)]
+        unset: Vec<SettingColumn>,
+    },
+    /// Give a profile a new name, keeping its settings
+    Rename {
+        old: String,
+        new: String,
+    },
+    /// Save a profile's settings under a new name
+    Copy {
+        source: String,
+        destination: String,
+    },
+    /// Delete a profile after asking for confirmation
+    Delete {
+        name: String,
+        /// Delete without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Print a profile's settings
+    Show {
+        name: String,
+    },
+    /// Print the names of all profiles
+    List,
+}
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// Use the settings of this profile; other settings given override it for this run only
+    #[arg(short, long)]
+    pub profile: Option<String>,
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+    /// Generate this many different passwords with the same settings
+    #[arg(short, long, default_value_t = 1,
+          value_parser = RangedU64ValueParser::<usize>::new().range(1..=generation_logic::MAX_BATCH_SIZE as u64))]
+    pub count: usize,
+    /// How to print the passwords; json also gives the entropy, settings and profile
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Plain)]
+    pub format: OutputFormat,
+}
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Check the settings of this profile; other settings given override it
+    #[arg(short, long)]
+    pub profile: Option<String>,
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+}
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Profiles to export; all of them when none are given
+    pub names: Vec<String>,
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// JSON file to read, or `-` for stdin
+    pub file: String,
+}
+/// The generation settings that can be given on the command line. Every field is optional, so
+/// the settings that were actually given can be told apart from the defaults.
+#[derive(Args, Debug, Default, Clone, PartialEq)]
+pub struct SettingsArgs {
+    /// Minimum password length
+    #[arg(short = 'l', long = "minlength", value_parser = parse_count)]
+    pub minimum_length: Option<usize>,
+    /// Maximum length of the finished password, including digits, symbols and uppercase characters
+    #[arg(long = "maxlength", value_parser = parse_count)]
+    pub maximum_length: Option<usize>,
+    /// Exact length of the finished password; takes precedence over --minlength
+    #[arg(short = 'x', long = "exactlength", value_parser = parse_count)]
+    pub exact_length: Option<usize>,
+    /// Include at least one number (true) or none at all (false)
+    #[arg(long = "include_nums", value_name = "BOOL")]
+    pub include_numbers: Option<bool>,
+    /// Include at least one special character (true) or none at all (false)
+    #[arg(long = "include_spec", value_name = "BOOL")]
+    pub include_special: Option<bool>,
+    /// Include at least one uppercase character (true) or none at all (false)
+    #[arg(long = "include_ucase", value_name = "BOOL")]
+    pub include_ucase: Option<bool>,
+    /// Minimum number of digits (default 0)
+    #[arg(short = 'd', long = "min_digits", alias = "min-digits", value_parser = parse_count)]
+    pub min_numbers: Option<usize>,
+    /// Maximum number of digits; when set, the count is picked randomly between the minimum and this
+    #[arg(long = "max_digits", alias = "max-digits", value_parser = parse_count)]
+    pub max_numbers: Option<usize>,
+    /// Minimum number of special characters (default 0)
+    #[arg(short = 's', long = "min_special", alias = "min-special", value_parser = parse_count)]
+    pub min_special: Option<usize>,
+    /// Maximum number of special characters
+    #[arg(long = "max_special", alias = "max-special", value_parser = parse_count)]
+    pub max_special: Option<usize>,
+    /// Minimum number of uppercase characters (default 0)
+    #[arg(short = 'u', long = "min_ucase", alias = "min-ucase", value_parser = parse_count)]
+    pub min_ucase: Option<usize>,
+    /// Maximum number of uppercase characters
+    #[arg(long = "max_ucase", alias = "max-ucase", value_parser = parse_count)]
+    pub max_ucase: Option<usize>,
+    /// Characters to draw special characters from (default: the OWASP punctuation set)
+    #[arg(long = "symbols")]
+    pub symbol_set: Option<String>,
+    /// Leave out characters that are easy to confuse (l, 1, I, O, 0, |) and words containing them
+    #[arg(long = "exclude_ambiguous", value_name = "BOOL")]
+    pub exclude_ambiguous: Option<bool>,
+    /// Lengthen the password until its estimated entropy reaches this many bits
+    #[arg(short = 'e', long = "min_entropy", alias = "min-entropy", value_name = "BITS", value_parser = parse_min_entropy)]
+    pub min_entropy: Option<f64>,
+    /// Use dictionary words instead of random lowercase alphabetic characters
+    #[arg(long = "use_dict_words", value_name = "BOOL")]
+    pub use_words: Option<bool>,
+    /// Alternate consonants and vowels for a pronounceable non-word (overrides --use_dict_words)
+    #[arg(long = "pronounceable", value_name = "BOOL")]
+    pub pronounceable: Option<bool>,
+    /// Generate a passphrase of exactly this many words instead; length options are ignored
+    #[arg(short = 'w', long = "words", value_parser = parse_count)]
+    pub word_count: Option<usize>,
+    /// What goes between passphrase words (default hyphen)
+    #[arg(long = "separator", value_name = "none|space|hyphen|digit|symbol", value_parser = parse_separator)]
+    pub separator: Option<Separator>,
+    /// Derive the password for this site from a master secret instead of generating a random one
+    #[arg(long = "site")]
+    pub site: Option<String>,
+    /// Username to derive the password for
+    #[arg(long = "username")]
+    pub username: Option<String>,
+    /// Bump to get a new derived password for the same site and username (default 1)
+    #[arg(long = "counter")]
+    pub counter: Option<u32>,
+    /// Versioned derivation algorithm (default argon2id-chacha20-v1)
+    #[arg(long = "derivation", value_name = "NAME", value_parser = parse_derivation)]
+    pub derivation: Option<DerivationAlgorithm>,
+    /// Generate a token from random bytes instead of a password
+    #[arg(long = "token", value_name = "hex|base32|base32_nopad|base64url|uuid", value_parser = parse_token_format)]
+    pub token_format: Option<TokenFormat>,
+    /// Number of random bytes in a token (default 32)
+    #[arg(long = "token_bytes", value_parser = parse_count)]
+    pub token_bytes: Option<usize>,
+    /// Text to put in front of the token, e.g. sk_live_
+    #[arg(long = "token_prefix")]
+    pub token_prefix: Option<String>,
+    /// Append _ and a checksum of the token
+    #[arg(long = "token_checksum", value_name = "BOOL")]
+    pub token_checksum: Option<bool>,
+    /// Generate a numeric PIN with this many digits instead of a password
+    #[arg(long = "pin", value_name = "DIGITS", value_parser = parse_count)]
+    pub pin_length: Option<usize>,
+    /// Weak PINs to reject, from repeated, sequence, pattern, date and keypad (default all)
+    #[arg(long = "pin_rules", value_name = "all|none|LIST", value_parser = parse_pin_rules)]
+    pub pin_rules: Option<PinRules>,
+    /// Comma-separated PINs to reject on top of the rules
+    #[arg(long = "pin_reject", value_name = "LIST", value_parser = parse_pin_blocklist)]
+    pub pin_blocklist: Option<String>,
+    /// Shape of the password, e.g. Cvccvc-99-Cvccvc (a/A letter, c/C consonant, v/V vowel, x/X letter or digit, 9 digit, # symbol, w/W word, {n} repeats, \ escapes)
+    #[arg(long = "pattern", value_parser = parse_pattern)]
+    pub pattern: Option<String>,
+    /// Capitalization of dictionary and passphrase words (default lower)
+    #[arg(long = "word_style", value_name = "lower|title|random|camel|one_caps", value_parser = parse_word_style)]
+    pub word_style: Option<WordStyle>,
+    /// Swap some letters of words for look-alike digits
+    #[arg(long = "leetspeak", value_name = "BOOL")]
+    pub leetspeak: Option<bool>,
+    /// Language of the built-in word list (default en)
+    #[arg(long = "language", value_name = "en|de|es|fr|zh", value_parser = parse_language)]
+    pub language: Option<Language>,
+    /// Word list file to draw words from, one word per line or in EFF diceware format
+    #[arg(long = "word_list", value_name = "PATH")]
+    pub word_list_path: Option<String>,
+}
+/// Lengths and counts are stored as SQLite integers, which are signed 64-bit
+fn parse_count(count: &str) -> Result<usize, String> {
+    let count = count.parse::<usize>().map_err(|x| x.to_string())?;
+    if i64::try_from(count).is_err() {
+        return Err(format!("expected at most {}", i64::MAX));
+    }
+    Ok(count)
+}
+fn parse_min_entropy(bits: &str) -> Result<f64, String> {
+    let bits = bits.parse::<f64>().map_err(|x| x.to_string())?;
+    generation_logic::check_min_entropy(bits).map_err(|x| x.to_string())
+}
+fn parse_separator(name: &str) -> Result<Separator, String> {
+    Separator::from_name(name).ok_or_else(|| String::from("expected none, space, hyphen, digit or symbol"))
+}
+fn parse_derivation(name: &str) -> Result<DerivationAlgorithm, String> {
+    DerivationAlgorithm::from_name(name).ok_or_else(|| String::from("expected argon2id-chacha20-v1"))
+}
+fn parse_token_format(name: &str) -> Result<TokenFormat, String> {
+    TokenFormat::from_name(name).ok_or_else(|| String::from("expected hex, base32, base32_nopad, base64url or uuid"))
+}
+fn parse_pin_rules(names: &str) -> Result<PinRules, String> {
+    PinRules::from_names(names).ok_or_else(|| String::from("expected all, none or a list of repeated, sequence, pattern, date and keypad"))
+}
+fn parse_pin_blocklist(blocklist: &str) -> Result<String, String> {
+    pin::parse_blocklist(blocklist).map_err(|x| x.to_string())?;
+    Ok(String::from(blocklist))
+}
+fn parse_pattern(pattern: &str) -> Result<String, String> {
+    Pattern::parse(pattern).map_err(|x| x.to_string())?;
+    Ok(String::from(pattern))
+}
+fn parse_word_style(name: &str) -> Result<WordStyle, String> {
+    WordStyle::from_name(name).ok_or_else(|| String::from("expected lower, title, random, camel or one_caps"))
+}
+fn parse_language(name: &str) -> Result<Language, String> {
+    Language::from_name(name).ok_or_else(|| String::from("expected en, de, es, fr or zh"))
+}
+fn parse_setting_column(name: &str) -> Result<SettingColumn, String> {
+    SettingColumn::from_name(name).ok_or_else(|| String::from("expected a setting name as `export` writes it, such as pin_length"))
+}
+/// Applies a boolean `--include_*` option to a class minimum: including a class asks for at least
+/// one character of it, excluding it drops the minimum to zero
+fn include_class(minimum: &mut usize, include: bool) {
+    if include {
+        *minimum = (*minimum).max(1);
     }
     else{
-        Argument::Error
-    }
-}
-pub fn parse_args(args: Vec<String>) -> Vec<Argument>{
-    let mut arg_idx = 0;
-    let mut parsed_args: Vec<Argument> = Vec::new();
-    while arg_idx < args.len() {
-        let mut inc = 1;
-        let arg_type = args.get(arg_idx).unwrap();
-        if arg_idx < args.len() -1 {
-            if !is_arg(args.get(arg_idx + 1).unwrap()){
-                //if it isn't an argument type like --help, then try to parse a value from it
-                inc = 2;
-                let arg_val = args.get(arg_idx + 1).unwrap();
-                let parsed_argument = parse_single_arg(arg_type, arg_val);
-                if parsed_argument == Argument::Error {
-                    println!("error argument propagated to higher level");
-                }
-                parsed_args.push(parsed_argument);
-            }
-            else{
-                let parsed_argument = parse_single_arg(arg_type, "");
-                parsed_args.push(parsed_argument);
-            }
+        *minimum = 0;
+    }
+}
+impl SettingsArgs {
+    /// Overwrites the settings that were given on the command line. The `--include_*` options are
+    /// applied before the class minimums, so `--min_digits` wins over `--include_nums`.
+    pub fn apply(&self, generation_data: &mut GenerationData) {
+        if let Some(include) = self.include_numbers {
+            include_class(&mut generation_data.min_numbers, include);
         }
-        else{
-            let parsed_argument = parse_single_arg(arg_type, "");
-            parsed_args.push(parsed_argument);
+        if let Some(include) = self.include_special {
+            include_class(&mut generation_data.min_special, include);
+        }
+        if let Some(include) = self.include_ucase {
+            include_class(&mut generation_data.min_ucase, include);
+        }
+        if let Some(minimum_length) = self.minimum_length {
+            generation_data.minimum_length = minimum_length;
+        }
+        if self.maximum_length.is_some() {
+            generation_data.maximum_length = self.maximum_length;
+        }
+        if self.exact_length.is_some() {
+            generation_data.exact_length = self.exact_length;
+        }
+        if let Some(min_numbers) = self.min_numbers {
+            generation_data.min_numbers = min_numbers;
+        }
+        if self.max_numbers.is_some() {
+            generation_data.max_numbers = self.max_numbers;
+        }
+        if let Some(min_special) = self.min_special {
+            generation_data.min_special = min_special;
+        }
+        if self.max_special.is_some() {
+            generation_data.max_special = self.max_special;
+        }
+        if let Some(min_ucase) = self.min_ucase {
+            generation_data.min_ucase = min_ucase;
+        }
+        if self.max_ucase.is_some() {
+            generation_data.max_ucase = self.max_ucase;
+        }
+        if self.symbol_set.is_some() {
+            generation_data.symbol_set = self.symbol_set.clone();
+        }
+        if let Some(exclude_ambiguous) = self.exclude_ambiguous {
+            generation_data.exclude_ambiguous = exclude_ambiguous;
+        }
+        if self.min_entropy.is_some() {
+            generation_data.min_entropy = self.min_entropy;
+        }
+        if let Some(use_words) = self.use_words {
+            generation_data.use_words = use_words;
+        }
+        if let Some(pronounceable) = self.pronounceable {
+            generation_data.pronounceable = pronounceable;
+        }
+        if self.word_count.is_some() {
+            generation_data.word_count = self.word_count;
+        }
+        if let Some(separator) = self.separator {
+            generation_data.separator = separator;
+        }
+        if self.site.is_some() {
+            generation_data.site = self.site.clone();
+        }
+        if self.username.is_some() {
+            generation_data.username = self.username.clone();
+        }
+        if let Some(counter) = self.counter {
+            generation_data.counter = counter;
+        }
+        if let Some(derivation) = self.derivation {
+            generation_data.derivation = derivation;
+        }
+        if self.token_format.is_some() {
+            generation_data.token_format = self.token_format;
+        }
+        if self.token_bytes.is_some() {
+            generation_data.token_bytes = self.token_bytes;
+        }
+        if self.token_prefix.is_some() {
+            generation_data.token_prefix = self.token_prefix.clone();
+        }
+        if let Some(token_checksum) = self.token_checksum {
+            generation_data.token_checksum = token_checksum;
+        }
+        if self.pin_length.is_some() {
+            generation_data.pin_length = self.pin_length;
+        }
+        if let Some(pin_rules) = self.pin_rules {
+            generation_data.pin_rules = pin_rules;
+        }
+        if self.pin_blocklist.is_some() {
+            generation_data.pin_blocklist = self.pin_blocklist.clone();
+        }
+        if self.pattern.is_some() {
+            generation_data.pattern = self.pattern.clone();
+        }
+        if let Some(word_style) = self.word_style {
+            generation_data.word_style = word_style;
+        }
+        if let Some(leetspeak) = self.leetspeak {
+            generation_data.leetspeak = leetspeak;
+        }
+        if let Some(language) = self.language {
+            generation_data.language = language;
+        }
+        if self.word_list_path.is_some() {
+            generation_data.word_list_path = self.word_list_path.clone();
+            // a different file invalidates the hash recorded for the profile's list
+            generation_data.word_list_hash = None;
         }
-        arg_idx += inc;
     }
-    parsed_args
 }
-pub fn construct_features(input_arguments: Option<Vec<Argument>>) -> GenerationData {
+/// Default settings, adjusted by the environment variables (or `.env` entries) `MIN_LENGTH`,
+/// `MAX_LENGTH`, `INCLUDE_NUMBERS`, `INCLUDE_SPECIAL`, `INCLUDE_UCASE`, `EXCLUDE_AMBIGUOUS` and
+/// `USE_WORDS`. The lengths are checked like `--minlength` and `--maxlength`.
+fn env_defaults() -> Result<GenerationData, Error> {
     dotenv::dotenv().ok();
-    let mut minimum_length = 8;
-    let mut include_numbers = false;
-    let mut include_special = false;
-    let mut include_ucase = false;
-    let mut use_words = true;
-    if let Ok(env_min_length) = env::var("MIN_LENGTH"){
-        if let Ok(parsed_min_length) = env_min_length.parse::<usize>(){
-            minimum_length = parsed_min_length;
-        }
+    let mut generation_data = GenerationData::new(None, None, None, None, None, None);
+    if let Some(min_length) = env_count("MIN_LENGTH")? {
+        generation_data.minimum_length = min_length;
+    }
+    if let Some(max_length) = env_count("MAX_LENGTH")? {
+        generation_data.maximum_length = Some(max_length);
     }
     if let Ok(env_include_nums) = env::var("INCLUDE_NUMBERS"){
         if let Ok(parsed_include_nums) = env_include_nums.parse::<bool>(){
-            include_numbers = parsed_include_nums;
+            include_class(&mut generation_data.min_numbers, parsed_include_nums);
         }
     }
     if let Ok(env_include_spec) = env::var("INCLUDE_SPECIAL"){
         if let Ok(parsed_include_spec) = env_include_spec.parse::<bool>(){
-            include_special = parsed_include_spec;
+            include_class(&mut generation_data.min_special, parsed_include_spec);
         }
     }
     if let Ok(env_include_ucase) = env::var("INCLUDE_UCASE"){
         if let Ok(parsed_include_ucase) = env_include_ucase.parse::<bool>(){
-            include_ucase = parsed_include_ucase;
+            include_class(&mut generation_data.min_ucase, parsed_include_ucase);
+        }
+    }
+    if let Ok(env_exclude_ambiguous) = env::var("EXCLUDE_AMBIGUOUS"){
+        if let Ok(parsed_exclude_ambiguous) = env_exclude_ambiguous.parse::<bool>(){
+            generation_data.exclude_ambiguous = parsed_exclude_ambiguous;
         }
     }
     if let Ok(env_use_words) = env::var("USE_WORDS"){
         if let Ok(parsed_use_words) = env_use_words.parse::<bool>(){
-            use_words = parsed_use_words;
-        }
-    }
-    let mut save_data = false;
-    let mut new_profile: Option<String> = None;
-    let mut existing_profile: Option<String> = None;
-    let mut use_existing_profile = false;
-    if let Some(arguments) = input_arguments{
-        for arg in arguments {
-            match arg {
-                Argument::ParsedArgument(arg_type, arg_val) => {
-                    if let ArgValue::String(string_arg) = arg_val {
-                        match arg_type {
-                            ArgType::NewProfile => {
-                                save_data = true;
-                                new_profile = Some(string_arg);
-                            }
-                            ArgType::Profile => {
-                                use_existing_profile = true;
-                                existing_profile = Some(string_arg);
-                            }
-                            _ => {}
-                        }
-                    }
-                    else if let ArgValue::Bool(boolean_arg) = arg_val {
-                        match arg_type {
-                            ArgType::IncludeNumbers => {
-                                include_numbers = boolean_arg;
-                            },
-                            ArgType::IncludeSpecial => {
-                                include_special = boolean_arg;
-                            },
-                            ArgType::IncludeUcase => {
-                                include_ucase = boolean_arg;
-                            },
-                            ArgType::UseWords => {
-                                use_words = boolean_arg;
-                            },
-                            _ => {
-                                println!("invalid arg value for given arg type");
-                            }
-                        }
-                    }
-                    else if let ArgValue::Int(int_arg) = arg_val {
-                        match arg_type {
-                            ArgType::MinimumLength => {
-                                minimum_length = int_arg;
-                            },
-                            _ => {
-                                println!("invalid arg value for given arg type");
-                            }
-                        }
-                    }
+            generation_data.use_words = parsed_use_words;
+        }
+    }
+    Ok(generation_data)
+}
+/// Reads a length or count from the environment variable `name`, with the bounds of the matching
+/// command line option
+fn env_count(name: &str) -> Result<Option<usize>, Error> {
+    match env::var(name) {
+        Ok(value) => parse_count(&value).map(Some)
+            .map_err(|x| Error::Parse(format!("invalid value '{}' for {}: {}", value, name, x))),
+        Err(_) => Ok(None),
+    }
+}
+/// The settings to generate with: those of `profile` if one is given, otherwise the defaults,
+/// with the settings given on the command line on top
+pub fn construct_features(conn: &Connection, profile: Option<&String>, settings: &SettingsArgs) -> Result<GenerationData, Error> {
+    let mut generation_data = match profile {
+        Some(profile) => database::retrieve_profile_settings(conn, profile)?,
+        None => env_defaults()?,
+    };
+    settings.apply(&mut generation_data);
+    Ok(generation_data)
+}
+/// Records the hash of the settings' word list file, if they have one, so the profile they are
+/// saved to fails loudly if the file is edited later
+fn record_word_list_hash(generation_data: &mut GenerationData) -> Result<(), Error> {
+    if generation_data.word_list_path.is_some() {
+        let word_list = generation_data.load_word_list()?;
+        generation_data.word_list_hash = word_list.content_hash().map(String::from);
+    }
+    Ok(())
+}
+/// Location of the profile database below the XDG data directory
+const DATABASE_FILE: &str = "password-generator/profiles.db";
+/// Location of the config file below the XDG config directory
+const CONFIG_FILE: &str = "password-generator/config";
+
+/// A path from the environment; unset and empty variables are both treated as missing
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+/// The XDG base directory named by `variable`, or `fallback` below the home directory. Relative
+/// paths are ignored, as the XDG spec asks.
+fn xdg_directory(variable: &str, fallback: &str) -> Option<PathBuf> {
+    env_path(variable).filter(|directory| directory.is_absolute())
+        .or_else(|| env_path("HOME").map(|home| home.join(fallback)))
+}
+/// Reads the `DATABASE` entry of a config file made of `KEY=value` lines, where `#` starts a
+/// comment line and values may be quoted. A relative path is taken relative to the config file. A
+/// missing config file is not an error.
+fn config_database_path(config_file: &Path) -> Result<Option<PathBuf>, Error> {
+    let contents = match fs::read_to_string(config_file) {
+        Ok(contents) => contents,
+        Err(x) if x.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(x) => return Err(Error::Io(format!("could not read '{}': {}", config_file.display(), x))),
+    };
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| Error::Parse(format!("'{}' line {}: expected KEY=value", config_file.display(), number + 1)))?;
+        let value = value.trim();
+        let value = ['"', '\''].into_iter()
+            .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+            .unwrap_or(value);
+        if key.trim() == "DATABASE" {
+            let directory = config_file.parent().unwrap_or(Path::new(""));
+            return Ok(Some(directory.join(value)));
+        }
+    }
+    Ok(None)
+}
+/// Picks the first database location given: the `--db` option, `PASSWORD_GENERATOR_DB`, the
+/// config file, then `profiles.db` in the data directory
+fn resolve_database_path(option: Option<PathBuf>, variable: Option<PathBuf>, config: Option<PathBuf>, data_directory: Option<PathBuf>) -> Result<PathBuf, Error> {
+    option.or(variable).or(config)
+        .or_else(|| data_directory.map(|directory| directory.join(DATABASE_FILE)))
+        .ok_or_else(|| Error::Storage(String::from("no location for the profile database; use --db, or set PASSWORD_GENERATOR_DB or XDG_DATA_HOME")))
+}
+/// Where the profile database is, given the `--db` option. `PASSWORD_GENERATOR_DB` may also be
+/// set in `.env`.
+pub fn database_path(option: Option<PathBuf>) -> Result<PathBuf, Error> {
+    dotenv::dotenv().ok();
+    let config = match xdg_directory("XDG_CONFIG_HOME", ".config") {
+        Some(directory) => config_database_path(&directory.join(CONFIG_FILE))?,
+        None => None,
+    };
+    resolve_database_path(option, env_path("PASSWORD_GENERATOR_DB"), config, xdg_directory("XDG_DATA_HOME", ".local/share"))
+}
+/// Reads the master secret for derived passwords from `PASSWORD_GENERATOR_MASTER_SECRET`, or
+/// prompts for it on stdin. Like the other settings, the variable may come from a `.env` file in
+/// the working directory. It is never stored.
+pub fn read_master_secret() -> Result<String, Error> {
+    if let Ok(master_secret) = env::var("PASSWORD_GENERATOR_MASTER_SECRET") {
+        return Ok(master_secret);
+    }
+    eprint!("master secret: ");
+    io::stderr().flush().ok();
+    let master_secret = read_secret_line()
+        .map_err(|x| Error::Io(format!("could not read the master secret: {}", x)))?;
+    Ok(String::from(master_secret.trim_end_matches(['\r', '\n'])))
+}
+/// Reads a line from stdin with terminal echo turned off, so the secret doesn't show on screen or
+/// in the scrollback. Input that isn't a terminal is read as is.
+#[cfg(unix)]
+fn read_secret_line() -> io::Result<String> {
+    let mut line = String::new();
+    if !io::stdin().is_terminal() {
+        io::stdin().lock().read_line(&mut line)?;
+        return Ok(line);
+    }
+    let mut original = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: tcgetattr initializes the struct when it succeeds, which is checked before reading it
+    let original = unsafe {
+        if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        original.assume_init()
+    };
+    let mut hidden = original;
+    // still echo the newline, so whatever is printed next starts on a line of its own
+    hidden.c_lflag &= !libc::ECHO;
+    hidden.c_lflag |= libc::ECHONL;
+    // SAFETY: both calls only read the termios structs passed to them
+    unsafe {
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &hidden) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let read = io::stdin().lock().read_line(&mut line);
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original);
+        read?;
+    }
+    Ok(line)
+}
+/// Reads a line from stdin. There is no portable way to turn off echo here, so a terminal is
+/// refused rather than showing the secret as it is typed.
+#[cfg(not(unix))]
+fn read_secret_line() -> io::Result<String> {
+    if io::stdin().is_terminal() {
+        return Err(io::Error::new(io::ErrorKind::Unsupported,
+                                  "the secret can't be hidden on this terminal; set PASSWORD_GENERATOR_MASTER_SECRET instead"));
+    }
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line)
+}
+/// Asks a yes or no question on stderr and reads the answer from stdin. Anything but `y` or `yes`,
+/// including the end of input, counts as no.
+fn confirm(question: &str) -> Result<bool, Error> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().ok();
+    read_answer(&mut io::stdin().lock())
+}
+fn read_answer(input: &mut impl BufRead) -> Result<bool, Error> {
+    let mut answer = String::new();
+    input.read_line(&mut answer)
+        .map_err(|x| Error::Io(format!("could not read the answer: {}", x)))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+/// Applies the pending schema migrations, backing up the database file first. Every command but
+/// `db migrate` runs this before it starts.
+pub fn migrate(conn: &Connection, quiet: bool) -> Result<(), Error> {
+    if let Some(backup) = migrations::backup(conn)? {
+        output::status(quiet, &format!("Backed up the profile database to '{}'", backup.display()));
+    }
+    for step in migrations::migrate(conn)? {
+        output::status(quiet, &format!("Applied migration {}: {}", step.version, step.description));
+    }
+    Ok(())
+}
+/// Runs `command`. Results go to stdout; status messages go to stderr unless `quiet` is set.
+pub fn run_command(conn: &Connection, command: Command, quiet: bool) -> Result<(), Error> {
+    match command {
+        Command::Generate(args) => generate(conn, args, quiet),
+        Command::Profile(ProfileCommand::Create { name, settings }) => {
+            let mut generation_data = env_defaults()?;
+            settings.apply(&mut generation_data);
+            generation_data.profile = Some(name);
+            record_word_list_hash(&mut generation_data)?;
+            database::insert_user_profile(conn, &generation_data)?;
+            output::status(quiet, &format!("Saved profile '{}'", generation_data.profile.unwrap()));
+            Ok(())
+        }
+        Command::Profile(ProfileCommand::Update { name, settings, unset }) => {
+            if *settings == SettingsArgs::default() && unset.is_empty() {
+                return Err(Error::Parse(String::from("profile update needs at least one setting to change or unset")));
+            }
+            let stored = database::retrieve_profile_settings(conn, &name)?;
+            let mut generation_data = stored.clone();
+            for column in &unset {
+                column.reset(&mut generation_data)?;
+                // the hash only vouches for the file it was taken from
+                if *column == SettingColumn::WordListPath {
+                    SettingColumn::WordListHash.reset(&mut generation_data)?;
                 }
-                Argument::Error => { continue }
             }
+            settings.apply(&mut generation_data);
+            if settings.word_list_path.is_some() {
+                record_word_list_hash(&mut generation_data)?;
+            }
+            let changed = database::update_profile(conn, &name, &generation_data)?;
+            let (before, after) = (output::settings_json(&stored)?, output::settings_json(&generation_data)?);
+            for column in &changed {
+                println!("{}: {} -> {}", column.as_str(), before[column.as_str()], after[column.as_str()]);
+            }
+            if changed.is_empty() {
+                output::status(quiet, &format!("Profile '{}' already has these settings", name));
+            }
+            else{
+                output::status(quiet, &format!("Updated {} settings of profile '{}'", changed.len(), name));
+            }
+            Ok(())
         }
-    }
-    let mut generation_data = GenerationData::new(Some(minimum_length), 
-                        Some(include_numbers), 
-                        Some(include_special), 
-                        Some(include_ucase), 
-                        Some(use_words),
-                        None,
-                        Some(false));
-    if save_data == true {
-        println!("Saving current settings to user profile with the name: {}", new_profile.as_ref().unwrap());
-        //TODO: take into account the --override option. if the user already exists and the
-        //override option isn't set, then don't overwrite the profile with that particular name
-        generation_data.profile = new_profile;
-        let conn = database::create_connection();
-        let result = database::insert_user_profile(&conn, &generation_data);
-        match result {
-            Ok(_) => {
-                println!("Successfully saved profile");
+        Command::Profile(ProfileCommand::Rename { old, new }) => {
+            database::rename_profile(conn, &old, &new)?;
+            output::status(quiet, &format!("Renamed profile '{}' to '{}'", old, new));
+            Ok(())
+        }
+        Command::Profile(ProfileCommand::Copy { source, destination }) => {
+            database::copy_profile(conn, &source, &destination)?;
+            output::status(quiet, &format!("Copied profile '{}' to '{}'", source, destination));
+            Ok(())
+        }
+        Command::Profile(ProfileCommand::Delete { name, yes }) => {
+            // look the profile up first so a missing one is reported before the prompt
+            database::retrieve_profile_settings(conn, &name)?;
+            if !yes && !confirm(&format!("Delete profile '{}'?", name))? {
+                return Err(Error::NotConfirmed(format!("deleting profile '{}'", name)));
+            }
+            database::delete_user_profile(conn, &name)?;
+            output::status(quiet, &format!("Deleted profile '{}'", name));
+            Ok(())
+        }
+        Command::Db(DbCommand::Migrate { dry_run: true }) => {
+            let steps = migrations::pending(conn)?;
+            for step in steps {
+                println!("{}: {}", step.version, step.description);
             }
-            Err(_) => {
-                println!("Error saving profile.");
+            if steps.is_empty() {
+                output::status(quiet, &format!("The profile database is up to date at version {}", migrations::schema_version(conn)?));
             }
+            Ok(())
+        }
+        Command::Db(DbCommand::Migrate { dry_run: false }) => {
+            migrate(conn, quiet)?;
+            output::status(quiet, &format!("The profile database is at version {}", migrations::schema_version(conn)?));
+            Ok(())
+        }
+        Command::Profile(ProfileCommand::Show { name }) => database::print_single_profile(conn, &name),
+        Command::Profile(ProfileCommand::List) => database::print_profiles(conn),
+        Command::Check(args) => {
+            let generation_data = construct_features(conn, args.profile.as_ref(), &args.settings)?;
+            // a trial run catches what the entropy estimate can't, such as length limits that no
+            // combination of words fits
+            generation_data.generate_password()?;
+            let entropy = generation_data.entropy_bits()?;
+            println!("settings are valid; estimated entropy: {:.1} bits", entropy);
+            Ok(())
         }
-        conn.close().unwrap();
-    }
-    else if use_existing_profile == true {
-        let conn = database::create_connection();
-        let profile_settings = database::retrieve_profile_settings(&conn, &existing_profile.unwrap());
-        conn.close().unwrap();
-        return profile_settings.unwrap()
-    }
-    generation_data
-}
-/// Processes an argument vector and allows for early exit in the case of certain arguments,
-/// like '--help'; avoids the need to process these arguments ahead of time in the 'main' module
-pub fn process_and_execute_args(input_args: Option<Vec<Argument>>) -> GenerationData {
-    match input_args {
-        Some(ref parsed_args) => {
-            for arg in parsed_args {
-                match arg {
-                    Argument::ParsedArgument(ArgType::Help, _) => {
-                        println!("A command line tool to generate a random password with given parameters");
-                        println!("Options:");
-                        println!("--help: print the available command line options");
-                        println!("--minlength <int> : min password length");
-                        println!("--include_nums <bool> : include numbers in password");
-                        println!("--include_spec <bool> : include special characters in password");
-                        println!("--include_ucase <bool> : include uppercase characters in password");
-                        println!("--use_dict_words <bool> : use dictionary words instead of random lowercase alphabetic characters");
-                        println!("--profile <string> : name of the profile to use. Will pull from local database if such a profile exists");
-                        println!("--new_profile <string> store a new profile with the provided settings using the given name");
-                        println!("--overwrite <bool> : if using a profile, overwrite its current settings with the other command line options");
-                        println!("--list_profiles : prints a list of available profiles");
-                        println!("--profile_info <string> : prints the provided profile's settings, if the profile exists.");
-                        exit(1);
-                    }
-                    Argument::ParsedArgument(ArgType::ListProfiles, _) => {
-                        let conn = database::create_connection();
-                        database::print_profiles(&conn);
-                        conn.close().unwrap();
-                        exit(1);
-                    }
-                    Argument::ParsedArgument(ArgType::ProfileInfo, ArgValue::String(profile_name)) => {
-                        let conn = database::create_connection();
-                        database::print_single_profile(&conn, &profile_name);
-                        conn.close().unwrap();
-                        exit(1);
-                    }
-                    /*
-                    Argument::ParsedArgument(ArgType::NewProfile, ArgValue::String(profile_name)) => {
-                        let conn = database::create_connection();
-                        database::print_single_profile(&conn, &profile_name);
-                        conn.close().unwrap();
-                        exit(1);
-                    }
-                    */
-                    _ => {
-                        continue;
-                    }
+        Command::Export(args) => {
+            let names = if args.names.is_empty() {database::profile_names(conn)?} else {args.names};
+            let mut profiles = Vec::new();
+            for name in &names {
+                let generation_data = database::retrieve_profile_settings(conn, name)?;
+                profiles.push(json!({"profile": name, "settings": output::settings_json(&generation_data)?}));
+            }
+            let exported = serde_json::to_string_pretty(&json!({"profiles": profiles})).expect("JSON values always serialize");
+            match args.output {
+                Some(path) => {
+                    fs::write(&path, exported + "\n")
+                        .map_err(|x| Error::Io(format!("could not write '{}': {}", path, x)))?;
+                    output::status(quiet, &format!("Exported {} profiles to {}", names.len(), path));
                 }
+                None => println!("{}", exported),
             }
-            construct_features(input_args)
+            Ok(())
         }
-        None => {
-            construct_features(None)
+        Command::Import(args) => {
+            let mut text = String::new();
+            let read = if args.file == "-" {
+                io::stdin().read_to_string(&mut text).map(|_| ())
+            }
+            else{
+                fs::read_to_string(&args.file).map(|contents| text = contents)
+            };
+            read.map_err(|x| Error::Io(format!("could not read '{}': {}", args.file, x)))?;
+            let imported: Value = serde_json::from_str(&text)
+                .map_err(|x| Error::Parse(format!("'{}' is not valid JSON: {}", args.file, x)))?;
+            let profiles = imported["profiles"].as_array()
+                .ok_or_else(|| Error::Parse(format!("'{}' has no \"profiles\" array", args.file)))?;
+            // all or nothing, so a bad entry halfway doesn't leave half the file imported
+            let transaction = conn.unchecked_transaction()?;
+            for profile in profiles {
+                let name = profile["profile"].as_str()
+                    .ok_or_else(|| Error::Parse(String::from("every imported profile needs a \"profile\" name")))?;
+                let mut generation_data = output::settings_from_json(&profile["settings"])
+                    .map_err(|x| Error::Parse(format!("profile '{}': {}", name, x)))?;
+                generation_data.profile = Some(String::from(name));
+                database::insert_user_profile(&transaction, &generation_data)?;
+            }
+            transaction.commit()?;
+            output::status(quiet, &format!("Imported {} profiles", profiles.len()));
+            Ok(())
         }
     }
 }
+/// Generates and prints passwords with the settings and output options in `args`
+fn generate(conn: &Connection, args: GenerateArgs, quiet: bool) -> Result<(), Error> {
+    let generation_features = construct_features(conn, args.profile.as_ref(), &args.settings)?;
+    let passwords = match generation_features.site {
+        Some(_) if args.count > 1 => {
+            return Err(Error::Parse(String::from("--count can't be used with --site; a derived password is always the same, bump --counter instead")));
+        }
+        Some(_) => vec![generation_features.derive_password(read_master_secret()?.as_bytes())?],
+        None => generation_features.generate_batch(args.count)?,
+    };
+    let entropy = generation_features.entropy_bits()?;
+    if args.format == OutputFormat::Plain {
+        if passwords.len() == 1 {
+            output::status(quiet, "printing the newly generated password: ");
+        }
+        else{
+            output::status(quiet, &format!("printing {} newly generated passwords: ", passwords.len()));
+        }
+    }
+    println!("{}", output::format_passwords(&passwords, entropy, &generation_features, args.format)?);
+    if args.format == OutputFormat::Plain {
+        output::status(quiet, &format!("estimated entropy: {:.1} bits", entropy));
+    }
+    Ok(())
+}
 #[cfg(test)]
 mod tests {
     use super::{
-        ArgType,
-        ArgValue,
-        Argument,
-        parse_single_arg,
-        parse_args
+        config_database_path,
+        construct_features,
+        env_count,
+        read_answer,
+        resolve_database_path,
+        run_command,
+        Cli,
+        Command,
+        DbCommand,
+        ProfileCommand,
+        SettingsArgs,
     };
-    //TODO: include a test for options that don't take arguments, like --help: make sure it's
-    //properly recognized. 
+    use clap::error::ErrorKind;
+    use password_generator::{database, migrations, Error, GenerationData};
+    use password_generator::generation_logic::WordStyle;
+    use crate::output::OutputFormat;
+    use rusqlite::Connection;
+    use std::path::PathBuf;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_args(std::iter::once("password_generator").chain(args.iter().copied()))
+    }
+    fn create_connection() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        database::initialize_db(&conn).unwrap();
+        conn
+    }
     #[test]
     fn detect_help_argument() {
-        let help_arg = parse_single_arg("--help", "");
-        assert_eq!(help_arg, Argument::ParsedArgument(ArgType::Help, ArgValue::NoValue));
-        let args_vec = vec![String::from("--help")];
-        let parsed_help_arg_vec = parse_args(args_vec);
-        assert_eq!(vec![help_arg], parsed_help_arg_vec);
+        assert_eq!(parse(&["--help"]).unwrap_err().kind(), ErrorKind::DisplayHelp);
+        assert_eq!(parse(&["profile", "create", "--help"]).unwrap_err().kind(), ErrorKind::DisplayHelp);
+    }
+    #[test]
+    fn flags_without_a_command_generate() {
+        let cli = parse(&["--minlength", "12", "-d", "2", "--count=5", "-f", "csv"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.generate.settings.minimum_length, Some(12));
+        assert_eq!(cli.generate.settings.min_numbers, Some(2));
+        assert_eq!(cli.generate.count, 5);
+        assert_eq!(cli.generate.format, OutputFormat::Csv);
+        let cli = parse(&["generate", "-l=12", "--quiet"]).unwrap();
+        assert!(cli.quiet);
+        match cli.command {
+            Some(Command::Generate(args)) => assert_eq!(args.settings.minimum_length, Some(12)),
+            _ => panic!(),
+        }
+    }
+    #[test]
+    fn global_options_go_on_either_side_of_the_command() {
+        for args in [&["-q", "profile", "list"][..], &["profile", "list", "--quiet"], &["--quiet", "generate", "-l", "12"]] {
+            let cli = parse(args).unwrap();
+            assert!(cli.quiet);
+            assert!(cli.command.is_some());
+        }
+        let error = parse(&["--minlength", "12", "profile", "list"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+        assert!(error.to_string().contains("--minlength"));
+        assert!(parse(&["-p", "work", "check"]).is_err());
+    }
+    #[test]
+    fn values_keep_their_type() {
+        // profile names and symbol sets that look like numbers or booleans stay strings
+        let cli = parse(&["--profile", "8"]).unwrap();
+        assert_eq!(cli.generate.profile, Some(String::from("8")));
+        match parse(&["profile", "create", "true", "--symbols", "007"]).unwrap().command {
+            Some(Command::Profile(ProfileCommand::Create { name, settings })) => {
+                assert_eq!(name, "true");
+                assert_eq!(settings.symbol_set, Some(String::from("007")));
+            }
+            _ => panic!(),
+        }
+        assert!(parse(&["--profile", "--help"]).is_err());
+    }
+    #[test]
+    fn errors_name_the_bad_token() {
+        let message = parse(&["--minlength", "8", "--bogus"]).unwrap_err().to_string();
+        assert!(message.contains("--bogus"));
+        let message = parse(&["--minlength", "true"]).unwrap_err().to_string();
+        assert!(message.contains("true") && message.contains("--minlength"));
+        let message = parse(&["--separator", "comma"]).unwrap_err().to_string();
+        assert!(message.contains("comma") && message.contains("expected none"));
+        assert!(parse(&["--count", "0"]).is_err());
+        assert!(parse(&["--count", "10001"]).is_err());
+        assert!(parse(&["--count", "9223372036854775807"]).is_err());
+        assert_eq!(parse(&["--min-entropy", "60"]).unwrap().generate.settings.min_entropy, Some(60.0));
+        assert_eq!(parse(&["--min_entropy", "60"]).unwrap().generate.settings.min_entropy, Some(60.0));
+        for bits in ["inf", "NaN", "-5", "100000"] {
+            assert!(parse(&["--min_entropy", bits]).is_err());
+        }
+        assert!(parse(&["--format", "xml"]).is_err());
+        assert!(parse(&["--maxlength", "18446744073709551615"]).is_err());
+    }
+    #[test]
+    fn environment_lengths_are_checked_like_options() {
+        assert_eq!(env_count("PASSWORD_GENERATOR_TEST_UNSET").unwrap(), None);
+        std::env::set_var("PASSWORD_GENERATOR_TEST_LENGTH", "16");
+        assert_eq!(env_count("PASSWORD_GENERATOR_TEST_LENGTH").unwrap(), Some(16));
+        for value in ["18446744073709551615", "-3", "long"] {
+            std::env::set_var("PASSWORD_GENERATOR_TEST_LENGTH", value);
+            match env_count("PASSWORD_GENERATOR_TEST_LENGTH") {
+                Err(Error::Parse(message)) => assert!(message.contains("PASSWORD_GENERATOR_TEST_LENGTH"), "{}", message),
+                other => panic!("{:?}", other),
+            }
+        }
+        std::env::remove_var("PASSWORD_GENERATOR_TEST_LENGTH");
     }
-    //TODO: include a test for combinations of non-argument-taking and argument-taking options
-
     #[test]
-    fn parse_single_argument() {
-        let min_length_arg = parse_single_arg("--minlength", "8");
-        assert_eq!(min_length_arg, Argument::ParsedArgument(ArgType::MinimumLength, ArgValue::Int(8)));
-        let dict_words_arg = parse_single_arg("--use_dict_words", "true");
-        assert_eq!(dict_words_arg, Argument::ParsedArgument(ArgType::UseWords, ArgValue::Bool(true)));
+    fn class_count_options_set_minimums_and_maximums() {
+        let cli = parse(&["--min_digits", "2", "--max_digits", "4", "--min_special", "2", "--include_ucase", "true"]).unwrap();
+        let generation_data = construct_features(&create_connection(), None, &cli.generate.settings).unwrap();
+        assert_eq!(generation_data.min_numbers, 2);
+        assert_eq!(generation_data.max_numbers, Some(4));
+        assert_eq!(generation_data.min_special, 2);
+        assert_eq!(generation_data.min_ucase, 1);
     }
     #[test]
-    fn parse_args_from_vec() {
-        let args_vec = vec![String::from("--minlength"), String::from("8")];
-        let parsed_args = parse_args(args_vec);
-        let correct_parsed_args = vec![Argument::ParsedArgument(ArgType::MinimumLength, ArgValue::Int(8))];
-        assert_eq!(parsed_args, correct_parsed_args);
+    fn class_count_options_take_both_spellings() {
+        let underscore = parse(&["--min_digits", "2", "--max_special", "3", "--min_ucase", "1"]).unwrap();
+        let kebab = parse(&["--min-digits", "2", "--max-special", "3", "--min-ucase", "1"]).unwrap();
+        assert_eq!(underscore.generate.settings, kebab.generate.settings);
+        assert_eq!(kebab.generate.settings.min_numbers, Some(2));
+    }
+    #[test]
+    fn settings_override_a_profile() {
+        let conn = create_connection();
+        let mut stored = GenerationData::new(Some(20), Some(3), None, None, Some(false), Some(String::from("work")));
+        stored.exclude_ambiguous = true;
+        database::insert_user_profile(&conn, &stored).unwrap();
+        let cli = parse(&["-p", "work", "--min_digits", "1"]).unwrap();
+        let generation_data = construct_features(&conn, cli.generate.profile.as_ref(), &cli.generate.settings).unwrap();
+        assert_eq!(generation_data.minimum_length, 20);
+        assert_eq!(generation_data.min_numbers, 1);
+        assert!(generation_data.exclude_ambiguous);
+        let missing = String::from("home");
+        assert!(matches!(construct_features(&conn, Some(&missing), &cli.generate.settings), Err(Error::ProfileNotFound(_))));
+    }
+    #[test]
+    fn profile_update_takes_only_the_given_settings() {
+        match parse(&["profile", "update", "work", "--minlength=16", "--word_style", "title"]).unwrap().command {
+            Some(Command::Profile(ProfileCommand::Update { name, settings, unset })) => {
+                assert_eq!(name, "work");
+                assert_eq!(*settings, SettingsArgs { minimum_length: Some(16), word_style: Some(WordStyle::Title), ..SettingsArgs::default() });
+                assert!(unset.is_empty());
+            }
+            _ => panic!(),
+        }
+        let conn = create_connection();
+        let stored = GenerationData::new(Some(12), None, None, None, None, Some(String::from("work")));
+        database::insert_user_profile(&conn, &stored).unwrap();
+        let update = parse(&["profile", "update", "work"]).unwrap().command.unwrap();
+        assert!(matches!(run_command(&conn, update, true), Err(Error::Parse(_))));
+        let update = parse(&["profile", "update", "home", "-l", "16"]).unwrap().command.unwrap();
+        assert!(matches!(run_command(&conn, update, true), Err(Error::ProfileNotFound(_))));
+    }
+    #[test]
+    fn profile_update_unsets_settings() {
+        let conn = create_connection();
+        let mut stored = GenerationData::new(Some(12), None, None, None, None, Some(String::from("work")));
+        stored.pin_length = Some(6);
+        stored.max_numbers = Some(3);
+        database::insert_user_profile(&conn, &stored).unwrap();
+        let update = parse(&["profile", "update", "work", "--unset", "pin_length", "--unset", "max_numbers", "-l", "16"]).unwrap().command.unwrap();
+        run_command(&conn, update, true).unwrap();
+        let updated = database::retrieve_profile_settings(&conn, &String::from("work")).unwrap();
+        assert_eq!(updated.pin_length, None);
+        assert_eq!(updated.max_numbers, None);
+        assert_eq!(updated.minimum_length, 16);
+        assert!(parse(&["profile", "update", "work", "--unset", "pin_size"]).is_err());
+    }
+    #[test]
+    fn database_path_sources_are_tried_in_order() {
+        let path = |text: &str| Some(PathBuf::from(text));
+        assert_eq!(resolve_database_path(path("a.db"), path("b.db"), path("c.db"), path("/data")).unwrap(), PathBuf::from("a.db"));
+        assert_eq!(resolve_database_path(None, path("b.db"), path("c.db"), path("/data")).unwrap(), PathBuf::from("b.db"));
+        assert_eq!(resolve_database_path(None, None, path("c.db"), path("/data")).unwrap(), PathBuf::from("c.db"));
+        assert_eq!(resolve_database_path(None, None, None, path("/data")).unwrap(),
+                   PathBuf::from("/data/password-generator/profiles.db"));
+        assert!(matches!(resolve_database_path(None, None, None, None), Err(Error::Storage(_))));
+        assert_eq!(parse(&["profile", "list", "--db", "work.db"]).unwrap().db, path("work.db"));
+        assert_eq!(parse(&["--db", "work.db", "profile", "list"]).unwrap().db, path("work.db"));
+        assert_eq!(parse(&["--db=work.db", "profile", "show", "home"]).unwrap().db, path("work.db"));
+    }
+    #[test]
+    fn config_file_names_the_database() {
+        let directory = std::env::temp_dir().join(format!("password_generator_config_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let config_file = directory.join("config");
+        assert_eq!(config_database_path(&config_file).unwrap(), None);
+        std::fs::write(&config_file, "# profiles live next to this file\nDATABASE=profiles.db\n").unwrap();
+        assert_eq!(config_database_path(&config_file).unwrap(), Some(directory.join("profiles.db")));
+        std::fs::write(&config_file, "LANGUAGE=de\nDATABASE = \"/srv/profiles.db\"\n").unwrap();
+        assert_eq!(config_database_path(&config_file).unwrap(), Some(PathBuf::from("/srv/profiles.db")));
+        std::fs::write(&config_file, "DATABASE\n").unwrap();
+        assert!(matches!(config_database_path(&config_file), Err(Error::Parse(_))));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+    #[test]
+    fn db_migrate_dry_run_leaves_the_database_alone() {
+        let conn = Connection::open_in_memory().unwrap();
+        match parse(&["db", "migrate", "--dry-run"]).unwrap().command {
+            Some(Command::Db(DbCommand::Migrate { dry_run })) => assert!(dry_run),
+            _ => panic!(),
+        }
+        run_command(&conn, parse(&["db", "migrate", "--dry-run"]).unwrap().command.unwrap(), true).unwrap();
+        assert_eq!(migrations::schema_version(&conn).unwrap(), 0);
+        run_command(&conn, parse(&["db", "migrate"]).unwrap().command.unwrap(), true).unwrap();
+        assert_eq!(migrations::schema_version(&conn).unwrap(), migrations::LATEST_VERSION);
+    }
+    #[test]
+    fn unreadable_and_unwritable_files_are_io_errors() {
+        let conn = create_connection();
+        let missing = std::env::temp_dir().join("password_generator_missing_dir").join("profiles.json");
+        let missing = missing.to_string_lossy();
+        let import = parse(&["import", &missing]).unwrap().command.unwrap();
+        assert!(matches!(run_command(&conn, import, true), Err(Error::Io(_))));
+        let export = parse(&["export", "--output", &missing]).unwrap().command.unwrap();
+        assert!(matches!(run_command(&conn, export, true), Err(Error::Io(_))));
+    }
+    #[test]
+    fn profile_rename_copy_and_delete() {
+        let conn = create_connection();
+        let stored = GenerationData::new(Some(12), None, None, None, None, Some(String::from("work")));
+        database::insert_user_profile(&conn, &stored).unwrap();
+        let run = |args: &[&str]| run_command(&conn, parse(args).unwrap().command.unwrap(), true);
+        run(&["profile", "copy", "work", "home"]).unwrap();
+        assert!(matches!(run(&["profile", "copy", "work", "home"]), Err(Error::ProfileExists(_))));
+        assert!(matches!(run(&["profile", "rename", "home", "work"]), Err(Error::ProfileExists(_))));
+        run(&["profile", "rename", "home", "personal"]).unwrap();
+        assert!(matches!(run(&["profile", "rename", "home", "other"]), Err(Error::ProfileNotFound(_))));
+        assert!(matches!(run(&["profile", "delete", "home", "--yes"]), Err(Error::ProfileNotFound(_))));
+        run(&["profile", "delete", "work", "-y"]).unwrap();
+        assert_eq!(database::profile_names(&conn).unwrap(), vec![String::from("personal")]);
+        assert_eq!(database::retrieve_profile_settings(&conn, &String::from("personal")).unwrap().minimum_length, 12);
+    }
+    #[test]
+    fn only_yes_confirms() {
+        assert!(read_answer(&mut "y\n".as_bytes()).unwrap());
+        assert!(read_answer(&mut "Yes\n".as_bytes()).unwrap());
+        assert!(!read_answer(&mut "n\n".as_bytes()).unwrap());
+        assert!(!read_answer(&mut "".as_bytes()).unwrap());
+        assert_eq!(Error::NotConfirmed(String::from("deleting profile 'work'")).exit_code(), 8);
     }
 }
-
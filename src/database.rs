@@ -1,161 +1,550 @@
-use rusqlite::{Connection, Result, params};
+use rusqlite::{Connection, params, params_from_iter};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Value, ValueRef};
+use crate::error::Error;
 use crate::generation_logic;
+use crate::migrations;
+use crate::derive::DerivationAlgorithm;
+use crate::generation_logic::{Language, Separator, WordStyle};
+use crate::pin::PinRules;
+use crate::token::TokenFormat;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::Path;
 
-pub fn create_connection() -> Connection{
-    let conn = Connection::open("profiles_database.db").unwrap();
-    conn
+impl ToSql for Separator {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for Separator {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        Separator::from_name(name).ok_or_else(|| FromSqlError::Other(format!("unknown separator '{}'", name).into()))
+    }
+}
+impl ToSql for Language {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for Language {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        Language::from_name(name).ok_or_else(|| FromSqlError::Other(format!("unknown language '{}'", name).into()))
+    }
+}
+impl ToSql for WordStyle {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for WordStyle {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        WordStyle::from_name(name).ok_or_else(|| FromSqlError::Other(format!("unknown word style '{}'", name).into()))
+    }
+}
+impl ToSql for PinRules {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_names()))
+    }
+}
+impl FromSql for PinRules {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let names = value.as_str()?;
+        PinRules::from_names(names).ok_or_else(|| FromSqlError::Other(format!("unknown PIN rules '{}'", names).into()))
+    }
+}
+impl ToSql for DerivationAlgorithm {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for DerivationAlgorithm {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        DerivationAlgorithm::from_name(name).ok_or_else(|| FromSqlError::Other(format!("unknown derivation algorithm '{}'", name).into()))
+    }
+}
+impl ToSql for TokenFormat {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+impl FromSql for TokenFormat {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        TokenFormat::from_name(name).ok_or_else(|| FromSqlError::Other(format!("unknown token format '{}'", name).into()))
+    }
+}
+/// The columns of `password_settings` that hold a setting, named after the `GenerationData`
+/// fields they store. Column names can't be bound as SQL parameters, so statements that pick a
+/// column at run time take it from this whitelist rather than from a caller-supplied string.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SettingColumn {
+    MinimumLength,
+    MaximumLength,
+    ExactLength,
+    MinNumbers,
+    MaxNumbers,
+    MinSpecial,
+    MaxSpecial,
+    MinUcase,
+    MaxUcase,
+    SymbolSet,
+    ExcludeAmbiguous,
+    MinEntropy,
+    UseWords,
+    Pronounceable,
+    WordCount,
+    Separator,
+    Site,
+    Username,
+    Counter,
+    Derivation,
+    TokenFormat,
+    TokenBytes,
+    TokenPrefix,
+    TokenChecksum,
+    PinLength,
+    PinRules,
+    PinBlocklist,
+    Pattern,
+    WordStyle,
+    Leetspeak,
+    Language,
+    WordListPath,
+    WordListHash,
+}
+impl SettingColumn {
+    pub const ALL: [SettingColumn; 33] = [
+        SettingColumn::MinimumLength,
+        SettingColumn::MaximumLength,
+        SettingColumn::ExactLength,
+        SettingColumn::MinNumbers,
+        SettingColumn::MaxNumbers,
+        SettingColumn::MinSpecial,
+        SettingColumn::MaxSpecial,
+        SettingColumn::MinUcase,
+        SettingColumn::MaxUcase,
+        SettingColumn::SymbolSet,
+        SettingColumn::ExcludeAmbiguous,
+        SettingColumn::MinEntropy,
+        SettingColumn::UseWords,
+        SettingColumn::Pronounceable,
+        SettingColumn::WordCount,
+        SettingColumn::Separator,
+        SettingColumn::Site,
+        SettingColumn::Username,
+        SettingColumn::Counter,
+        SettingColumn::Derivation,
+        SettingColumn::TokenFormat,
+        SettingColumn::TokenBytes,
+        SettingColumn::TokenPrefix,
+        SettingColumn::TokenChecksum,
+        SettingColumn::PinLength,
+        SettingColumn::PinRules,
+        SettingColumn::PinBlocklist,
+        SettingColumn::Pattern,
+        SettingColumn::WordStyle,
+        SettingColumn::Leetspeak,
+        SettingColumn::Language,
+        SettingColumn::WordListPath,
+        SettingColumn::WordListHash,
+    ];
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SettingColumn::MinimumLength => "minimum_length",
+            SettingColumn::MaximumLength => "maximum_length",
+            SettingColumn::ExactLength => "exact_length",
+            SettingColumn::MinNumbers => "min_numbers",
+            SettingColumn::MaxNumbers => "max_numbers",
+            SettingColumn::MinSpecial => "min_special",
+            SettingColumn::MaxSpecial => "max_special",
+            SettingColumn::MinUcase => "min_ucase",
+            SettingColumn::MaxUcase => "max_ucase",
+            SettingColumn::SymbolSet => "symbol_set",
+            SettingColumn::ExcludeAmbiguous => "exclude_ambiguous",
+            SettingColumn::MinEntropy => "min_entropy",
+            SettingColumn::UseWords => "use_words",
+            SettingColumn::Pronounceable => "pronounceable",
+            SettingColumn::WordCount => "word_count",
+            SettingColumn::Separator => "separator",
+            SettingColumn::Site => "site",
+            SettingColumn::Username => "username",
+            SettingColumn::Counter => "counter",
+            SettingColumn::Derivation => "derivation",
+            SettingColumn::TokenFormat => "token_format",
+            SettingColumn::TokenBytes => "token_bytes",
+            SettingColumn::TokenPrefix => "token_prefix",
+            SettingColumn::TokenChecksum => "token_checksum",
+            SettingColumn::PinLength => "pin_length",
+            SettingColumn::PinRules => "pin_rules",
+            SettingColumn::PinBlocklist => "pin_blocklist",
+            SettingColumn::Pattern => "pattern",
+            SettingColumn::WordStyle => "word_style",
+            SettingColumn::Leetspeak => "leetspeak",
+            SettingColumn::Language => "language",
+            SettingColumn::WordListPath => "word_list_path",
+            SettingColumn::WordListHash => "word_list_hash",
+        }
+    }
+    /// Whether the column holds a yes or no setting, which SQLite stores as an integer
+    pub fn is_flag(&self) -> bool {
+        matches!(self, SettingColumn::ExcludeAmbiguous | SettingColumn::UseWords | SettingColumn::Pronounceable
+                 | SettingColumn::TokenChecksum | SettingColumn::Leetspeak)
+    }
+    /// The column called `name`
+    pub fn from_name(name: &str) -> Option<SettingColumn> {
+        SettingColumn::ALL.into_iter().find(|column| column.as_str() == name)
+    }
+    /// The value `settings` stores in this column
+    pub fn value(&self, settings: &generation_logic::GenerationData) -> rusqlite::Result<Value> {
+        let output = match self {
+            SettingColumn::MinimumLength => settings.minimum_length.to_sql(),
+            SettingColumn::MaximumLength => settings.maximum_length.to_sql(),
+            SettingColumn::ExactLength => settings.exact_length.to_sql(),
+            SettingColumn::MinNumbers => settings.min_numbers.to_sql(),
+            SettingColumn::MaxNumbers => settings.max_numbers.to_sql(),
+            SettingColumn::MinSpecial => settings.min_special.to_sql(),
+            SettingColumn::MaxSpecial => settings.max_special.to_sql(),
+            SettingColumn::MinUcase => settings.min_ucase.to_sql(),
+            SettingColumn::MaxUcase => settings.max_ucase.to_sql(),
+            SettingColumn::SymbolSet => settings.symbol_set.to_sql(),
+            SettingColumn::ExcludeAmbiguous => settings.exclude_ambiguous.to_sql(),
+            SettingColumn::MinEntropy => settings.min_entropy.to_sql(),
+            SettingColumn::UseWords => settings.use_words.to_sql(),
+            SettingColumn::Pronounceable => settings.pronounceable.to_sql(),
+            SettingColumn::WordCount => settings.word_count.to_sql(),
+            SettingColumn::Separator => settings.separator.to_sql(),
+            SettingColumn::Site => settings.site.to_sql(),
+            SettingColumn::Username => settings.username.to_sql(),
+            SettingColumn::Counter => settings.counter.to_sql(),
+            SettingColumn::Derivation => settings.derivation.to_sql(),
+            SettingColumn::TokenFormat => settings.token_format.to_sql(),
+            SettingColumn::TokenBytes => settings.token_bytes.to_sql(),
+            SettingColumn::TokenPrefix => settings.token_prefix.to_sql(),
+            SettingColumn::TokenChecksum => settings.token_checksum.to_sql(),
+            SettingColumn::PinLength => settings.pin_length.to_sql(),
+            SettingColumn::PinRules => settings.pin_rules.to_sql(),
+            SettingColumn::PinBlocklist => settings.pin_blocklist.to_sql(),
+            SettingColumn::Pattern => settings.pattern.to_sql(),
+            SettingColumn::WordStyle => settings.word_style.to_sql(),
+            SettingColumn::Leetspeak => settings.leetspeak.to_sql(),
+            SettingColumn::Language => settings.language.to_sql(),
+            SettingColumn::WordListPath => settings.word_list_path.to_sql(),
+            SettingColumn::WordListHash => settings.word_list_hash.to_sql(),
+        }?;
+        Ok(match output {
+            ToSqlOutput::Borrowed(value) => value.into(),
+            ToSqlOutput::Owned(value) => value,
+            _ => unreachable!("settings are stored as plain values"),
+        })
+    }
+    /// Stores `value`, as read from this column, in `settings`
+    pub fn set_value(&self, settings: &mut generation_logic::GenerationData, value: &Value) -> rusqlite::Result<()> {
+        match self {
+            SettingColumn::MinimumLength => settings.minimum_length = from_value(value)?,
+            SettingColumn::MaximumLength => settings.maximum_length = from_value(value)?,
+            SettingColumn::ExactLength => settings.exact_length = from_value(value)?,
+            SettingColumn::MinNumbers => settings.min_numbers = from_value(value)?,
+            SettingColumn::MaxNumbers => settings.max_numbers = from_value(value)?,
+            SettingColumn::MinSpecial => settings.min_special = from_value(value)?,
+            SettingColumn::MaxSpecial => settings.max_special = from_value(value)?,
+            SettingColumn::MinUcase => settings.min_ucase = from_value(value)?,
+            SettingColumn::MaxUcase => settings.max_ucase = from_value(value)?,
+            SettingColumn::SymbolSet => settings.symbol_set = from_value(value)?,
+            SettingColumn::ExcludeAmbiguous => settings.exclude_ambiguous = from_value(value)?,
+            SettingColumn::MinEntropy => settings.min_entropy = from_value(value)?,
+            SettingColumn::UseWords => settings.use_words = from_value(value)?,
+            SettingColumn::Pronounceable => settings.pronounceable = from_value(value)?,
+            SettingColumn::WordCount => settings.word_count = from_value(value)?,
+            SettingColumn::Separator => settings.separator = from_value(value)?,
+            SettingColumn::Site => settings.site = from_value(value)?,
+            SettingColumn::Username => settings.username = from_value(value)?,
+            SettingColumn::Counter => settings.counter = from_value(value)?,
+            SettingColumn::Derivation => settings.derivation = from_value(value)?,
+            SettingColumn::TokenFormat => settings.token_format = from_value(value)?,
+            SettingColumn::TokenBytes => settings.token_bytes = from_value(value)?,
+            SettingColumn::TokenPrefix => settings.token_prefix = from_value(value)?,
+            SettingColumn::TokenChecksum => settings.token_checksum = from_value(value)?,
+            SettingColumn::PinLength => settings.pin_length = from_value(value)?,
+            SettingColumn::PinRules => settings.pin_rules = from_value(value)?,
+            SettingColumn::PinBlocklist => settings.pin_blocklist = from_value(value)?,
+            SettingColumn::Pattern => settings.pattern = from_value(value)?,
+            SettingColumn::WordStyle => settings.word_style = from_value(value)?,
+            SettingColumn::Leetspeak => settings.leetspeak = from_value(value)?,
+            SettingColumn::Language => settings.language = from_value(value)?,
+            SettingColumn::WordListPath => settings.word_list_path = from_value(value)?,
+            SettingColumn::WordListHash => settings.word_list_hash = from_value(value)?,
+        }
+        Ok(())
+    }
+    /// Puts the value a new profile starts out with back into `settings`
+    pub fn reset(&self, settings: &mut generation_logic::GenerationData) -> rusqlite::Result<()> {
+        let defaults = generation_logic::GenerationData::new(None, None, None, None, None, None);
+        self.set_value(settings, &self.value(&defaults)?)
+    }
+}
+fn from_value<T: FromSql>(value: &Value) -> rusqlite::Result<T> {
+    T::column_result(value.into()).map_err(|x| rusqlite::Error::FromSqlConversionFailure(0, value.data_type(), Box::new(x)))
+}
+/// Opens the profile database at `path`. Missing parent directories are created readable by the
+/// owner only (0700), and so is a new database file (0600), since profiles may name the sites and
+/// usernames passwords are derived for. Existing files and directories keep their permissions.
+pub fn create_connection(path: &Path) -> Result<Connection, Error> {
+    let storage_error = |x: io::Error| Error::Storage(format!("could not create '{}': {}", path.display(), x));
+    if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(directory).map_err(storage_error)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    match options.open(path) {
+        Ok(_) => {},
+        Err(x) if x.kind() == io::ErrorKind::AlreadyExists => {},
+        Err(x) => return Err(storage_error(x)),
+    }
+    Ok(Connection::open(path)?)
 }
 
-pub fn initialize_db(conn: &Connection) -> Result<()> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS password_settings (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            profile_name TEXT UNIQUE NOT NULL,
-            minimum_length INTEGER DEFAULT 8,
-            include_numbers BOOLEAN DEFAULT false,
-            include_special BOOLEAN DEFAULT false,
-            include_ucase BOOLEAN DEFAULT false,
-            use_words BOOLEAN DEFAULT true,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-        []
-        )?;
+/// Brings the profile database up to the latest schema; see `migrations`
+pub fn initialize_db(conn: &Connection) -> Result<(), Error> {
+    migrations::migrate(conn)?;
     Ok(())
 }
-fn modify_single_setting(conn: &Connection, profile_name: &String, col: &String, val: &String) -> Result<()> {
-    //TODO: 'val' param shouldn't be string; should be enum as outlined in cli module
-    conn.execute(
-        "UPDATE password_settings (
-            SET ?2 = ?3
-        )
-        WHERE profile_name = ?1;
-        ",
-        &[profile_name, col, val]
+/// Sets one column of a profile, returning `Error::ProfileNotFound` if there is no such profile
+pub fn modify_single_setting(conn: &Connection, profile_name: &String, column: SettingColumn, value: &dyn ToSql) -> Result<(), Error> {
+    let updated = conn.execute(
+        &format!("UPDATE password_settings SET {} = ?2 WHERE profile_name = ?1;", column.as_str()),
+        params![profile_name, value]
         )?;
+    if updated == 0 {
+        return Err(Error::ProfileNotFound(profile_name.clone()));
+    }
     Ok(())
 }
-fn delete_user_profile(conn: &Connection, profile_name: &String) -> Result<()> {
-    conn.execute(
+/// Stores `settings` for an existing profile, writing only the columns whose value differs from
+/// what is stored. All columns change together or not at all. Returns the changed columns.
+pub fn update_profile(conn: &Connection, profile_name: &String, settings: &generation_logic::GenerationData) -> Result<Vec<SettingColumn>, Error> {
+    let transaction = conn.unchecked_transaction()?;
+    let stored = retrieve_profile_settings(&transaction, profile_name)?;
+    let mut changed = Vec::new();
+    for column in SettingColumn::ALL {
+        let value = column.value(settings)?;
+        if column.value(&stored)? != value {
+            modify_single_setting(&transaction, profile_name, column, &value)?;
+            changed.push(column);
+        }
+    }
+    transaction.commit()?;
+    Ok(changed)
+}
+/// Deletes a profile, returning `Error::ProfileNotFound` if there is no such profile
+pub fn delete_user_profile(conn: &Connection, profile_name: &String) -> Result<(), Error> {
+    let deleted = conn.execute(
         "DELETE FROM password_settings 
         WHERE profile_name = ?1;
         ",
-        &[profile_name]
+        [profile_name]
         )?;
+    if deleted == 0 {
+        return Err(Error::ProfileNotFound(profile_name.clone()));
+    }
     Ok(())
 }
-pub fn print_single_profile(conn: &Connection, profile_name: &String) {
-    let profile_settings = retrieve_profile_settings(&conn, profile_name);
-    match profile_settings {
-        Some(profile_settings) => {
-            println!("Printing settings for profile: {}", profile_name);
-            println!("Minimum length: {}", profile_settings.minimum_length);
-            println!("Includes numbers? : {}", profile_settings.include_numbers);
-            println!("Includes special characters? : {}", profile_settings.include_special);
-            println!("Includes uppercase characters? : {}", profile_settings.include_ucase);
-            println!("Uses dictionary words instead of random lowercase alphabetic chars? : {}",
-                     profile_settings.use_words);
-        }
-        None => {
-            println!("Could not find the specified profile for printing.")
+/// Turns a violation of the `UNIQUE` constraint on `profile_name` into `Error::ProfileExists`
+fn name_conflict(err: rusqlite::Error, profile_name: &str) -> Error {
+    match err {
+        rusqlite::Error::SqliteFailure(failure, _) if failure.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE => {
+            Error::ProfileExists(String::from(profile_name))
         }
+        err => Error::Database(err),
     }
 }
-pub fn insert_user_profile(conn: &Connection, generation_features: &generation_logic::GenerationData) -> Result<()> {
-    let result = conn.execute(
-        "INSERT INTO password_settings 
-        (
-        profile_name,
-        minimum_length,
-        include_numbers,
-        include_special,
-        include_ucase,
-        use_words
-        )
-        VALUES (
-        ?1,
-        ?2,
-        ?3,
-        ?4,
-        ?5,
-        ?6
-        )
-        ",
-        params![generation_features.profile.as_ref().unwrap(),
-        &(generation_features.minimum_length),
-        &(generation_features.include_numbers),
-        &(generation_features.include_special),
-        &(generation_features.include_ucase),
-        &(generation_features.use_words)]
-        );
-    match result {
-        Ok(x) => {}
-        Err(x) => {
-            println!("error when inserting into database: {}", x);
+/// Renames a profile, keeping its settings. Returns `Error::ProfileNotFound` if `old_name` doesn't
+/// exist and `Error::ProfileExists` if `new_name` is taken.
+pub fn rename_profile(conn: &Connection, old_name: &String, new_name: &String) -> Result<(), Error> {
+    let renamed = conn.execute(
+        "UPDATE password_settings SET profile_name = ?2 WHERE profile_name = ?1;",
+        [old_name, new_name]
+        ).map_err(|err| name_conflict(err, new_name))?;
+    if renamed == 0 {
+        return Err(Error::ProfileNotFound(old_name.clone()));
+    }
+    Ok(())
+}
+/// Saves the settings of `source` as a new profile named `destination`
+pub fn copy_profile(conn: &Connection, source: &String, destination: &str) -> Result<(), Error> {
+    let mut settings = retrieve_profile_settings(conn, source)?;
+    settings.profile = Some(String::from(destination));
+    insert_user_profile(conn, &settings)
+}
+fn describe_count_range(minimum: usize, maximum: Option<usize>) -> String {
+    match maximum {
+        Some(maximum) => format!("between {} and {}", minimum, maximum),
+        None => format!("exactly {}", minimum),
+    }
+}
+pub fn print_single_profile(conn: &Connection, profile_name: &String) -> Result<(), Error> {
+    let profile_settings = retrieve_profile_settings(conn, profile_name)?;
+    println!("Printing settings for profile: {}", profile_name);
+    println!("Minimum length: {}", profile_settings.minimum_length);
+    if let Some(maximum_length) = profile_settings.maximum_length {
+        println!("Maximum length: {}", maximum_length);
+    }
+    if let Some(exact_length) = profile_settings.exact_length {
+        println!("Exact length: {}", exact_length);
+    }
+    println!("Numbers : {}", describe_count_range(profile_settings.min_numbers, profile_settings.max_numbers));
+    println!("Special characters : {}", describe_count_range(profile_settings.min_special, profile_settings.max_special));
+    println!("Uppercase characters : {}", describe_count_range(profile_settings.min_ucase, profile_settings.max_ucase));
+    println!("Special character set : {}",
+             profile_settings.symbol_set.as_deref().unwrap_or(generation_logic::DEFAULT_SYMBOLS));
+    println!("Excludes ambiguous characters? : {}", profile_settings.exclude_ambiguous);
+    if let Some(min_entropy) = profile_settings.min_entropy {
+        println!("Minimum entropy: {} bits", min_entropy);
+    }
+    println!("Uses dictionary words instead of random lowercase alphabetic chars? : {}",
+             profile_settings.use_words);
+    println!("Uses pronounceable syllables? : {}", profile_settings.pronounceable);
+    if let Some(word_count) = profile_settings.word_count {
+        println!("Passphrase words: {}", word_count);
+        println!("Passphrase separator: {}", profile_settings.separator.as_str());
+    }
+    if let Some(site) = &profile_settings.site {
+        println!("Derived for site: {}", site);
+        if let Some(username) = &profile_settings.username {
+            println!("Derived for username: {}", username);
+        }
+        println!("Counter: {}", profile_settings.counter);
+        println!("Derivation algorithm: {}", profile_settings.derivation.as_str());
+    }
+    if let Some(token_format) = profile_settings.token_format {
+        println!("Token format: {}", token_format.as_str());
+        if let Some(token_bytes) = profile_settings.token_bytes {
+            println!("Token bytes: {}", token_bytes);
+        }
+        if let Some(token_prefix) = &profile_settings.token_prefix {
+            println!("Token prefix: {}", token_prefix);
         }
+        println!("Token checksum? : {}", profile_settings.token_checksum);
+    }
+    if let Some(pin_length) = profile_settings.pin_length {
+        println!("PIN digits: {}", pin_length);
+        println!("Weak PIN rules: {}", profile_settings.pin_rules.as_names());
+        if let Some(pin_blocklist) = &profile_settings.pin_blocklist {
+            println!("Blocked PINs: {}", pin_blocklist);
+        }
+    }
+    if let Some(pattern) = &profile_settings.pattern {
+        println!("Pattern: {}", pattern);
+    }
+    println!("Word style: {}", profile_settings.word_style.as_str());
+    println!("Leetspeak? : {}", profile_settings.leetspeak);
+    if let Some(word_list_path) = &profile_settings.word_list_path {
+        println!("Word list file: {}", word_list_path);
+        if let Some(word_list_hash) = &profile_settings.word_list_hash {
+            println!("Word list SHA-256: {}", word_list_hash);
+        }
+    }
+    else{
+        println!("Word list language: {}", profile_settings.language.as_str());
+    }
+    Ok(())
+}
+pub fn insert_user_profile(conn: &Connection, generation_features: &generation_logic::GenerationData) -> Result<(), Error> {
+    let profile_name = generation_features.profile.as_ref()
+        .ok_or_else(|| Error::Parse(String::from("a profile name is required to save settings")))?;
+    let columns: Vec<&str> = SettingColumn::ALL.iter().map(|column| column.as_str()).collect();
+    let placeholders: Vec<String> = (2..=columns.len() + 1).map(|index| format!("?{}", index)).collect();
+    let mut values = vec![Value::Text(profile_name.clone())];
+    for column in SettingColumn::ALL {
+        values.push(column.value(generation_features)?);
     }
+    conn.execute(
+        &format!("INSERT INTO password_settings (profile_name, {}) VALUES (?1, {});", columns.join(", "), placeholders.join(", ")),
+        params_from_iter(values)
+        ).map_err(|err| name_conflict(err, profile_name))?;
     Ok(())
 }
-pub fn print_profiles(conn: &Connection) {
-    let mut statement = conn.prepare("SELECT profile_name FROM password_settings;").unwrap();
+/// Names of all saved profiles, in the order they were created
+pub fn profile_names(conn: &Connection) -> Result<Vec<String>, Error> {
+    let mut statement = conn.prepare("SELECT profile_name FROM password_settings ORDER BY id;")?;
     let profile_iter = statement.query_map([], |row| {
-        Ok(row.get::<_, String>(0)?)
-    }).unwrap();
-    for profile in profile_iter {
-        println!("{}", profile.unwrap());
+        row.get::<_, String>(0)
+    })?;
+    Ok(profile_iter.collect::<Result<Vec<String>, rusqlite::Error>>()?)
+}
+pub fn print_profiles(conn: &Connection) -> Result<(), Error> {
+    for profile in profile_names(conn)? {
+        println!("{}", profile);
     }
+    Ok(())
 }
 
 
 
-pub fn retrieve_profile_settings(conn: &Connection, profile_name: &String) -> Option<generation_logic::GenerationData>{
+/// Loads the stored settings for `profile_name`, returning `Error::ProfileNotFound` if there is
+/// no such profile.
+pub fn retrieve_profile_settings(conn: &Connection, profile_name: &String) -> Result<generation_logic::GenerationData, Error> {
     let result = conn.query_row(
         "
         SELECT * FROM password_settings
         WHERE profile_name = ?1;
         ",
-        &[&profile_name],
+        [profile_name],
         |row| {
-            let profile: String = row.get("profile_name").unwrap();
-            let minimum_length: usize = row.get("minimum_length").unwrap();
-            let include_numbers: bool = row.get("include_numbers").unwrap();
-            let include_special: bool = row.get("include_special").unwrap();
-            let include_ucase: bool = row.get("include_ucase").unwrap();
-            let use_words: bool = row.get("use_words").unwrap();
-            Ok(
-                generation_logic::GenerationData::new(
-                    Some(minimum_length),
-                    Some(include_numbers),
-                    Some(include_special),
-                    Some(include_ucase),
-                    Some(use_words),
-                    Some(profile),
-                    None
-                    )
-                )
+            let mut generation_data = generation_logic::GenerationData::new(None, None, None, None, None, Some(row.get("profile_name")?));
+            for column in SettingColumn::ALL {
+                column.set_value(&mut generation_data, &row.get(column.as_str())?)?;
+            }
+            Ok(generation_data)
         }
     );
     match result {
-        Ok(generation_data) => {Some(generation_data)}
-        Err(x) => {
-            println!("error retrieving user profile: ");
-            println!("{}", x);
-            None
-        }
+        Ok(generation_data) => Ok(generation_data),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::ProfileNotFound(profile_name.clone())),
+        Err(x) => Err(Error::Database(x)),
     }
 }
 #[cfg(test)]
 mod tests {
     use super::{
         retrieve_profile_settings,
-        create_connection,
         insert_user_profile,
         delete_user_profile,
         initialize_db,
+        modify_single_setting,
         print_profiles,
+        profile_names,
+        rename_profile,
+        copy_profile,
+        update_profile,
+        SettingColumn,
     };
+    use super::create_connection as open_database;
+    use crate::error::Error;
     use crate::generation_logic::{
-        GenerationData
+        GenerationData,
+        Language,
+        Separator,
+        WordStyle,
     };
+    use crate::pin::PinRules;
+    use crate::token::TokenFormat;
+    use rusqlite::Connection;
+
+    fn create_connection() -> Connection {
+        Connection::open_in_memory().unwrap()
+    }
 
     #[test]
     fn retrieve_settings_nonexistent_profile_should_return_none() {
@@ -163,8 +552,8 @@ mod tests {
         initialize_db(&conn).unwrap();
         let res = retrieve_profile_settings(&conn, &String::from("nonexistent_profile_name"));
         match res{
-            None => {},
-            Some(_) => {panic!();}
+            Err(Error::ProfileNotFound(_)) => {},
+            _ => {panic!();}
         }
     }
     #[test]
@@ -176,18 +565,144 @@ mod tests {
                                                None, 
                                                None, 
                                                None, 
-                                               Some(String::from("new_profile_name")), 
-                                               None);
-        let insertion_result = insert_user_profile(&conn, &default_user);
-        let retrieved_profile = retrieve_profile_settings(&conn, &default_user.profile.as_ref().unwrap()).unwrap();
-        print_profiles(&conn);
-        delete_user_profile(&conn, &default_user.profile.as_ref().unwrap()).unwrap(); // clean up afterwards
+                                               Some(String::from("new_profile_name")));
+        insert_user_profile(&conn, &default_user).unwrap();
+        let retrieved_profile = retrieve_profile_settings(&conn, default_user.profile.as_ref().unwrap()).unwrap();
+        print_profiles(&conn).unwrap();
+        delete_user_profile(&conn, default_user.profile.as_ref().unwrap()).unwrap(); // clean up afterwards
         assert_eq!(retrieved_profile, default_user);
     }
+    #[test]
+    fn insert_duplicate_profile_returns_error() {
+        let conn = create_connection();
+        initialize_db(&conn).unwrap();
+        let user = GenerationData::new(None, None, None, None, None, Some(String::from("duplicate_profile_name")));
+        insert_user_profile(&conn, &user).unwrap();
+        let second_insertion = insert_user_profile(&conn, &user);
+        delete_user_profile(&conn, user.profile.as_ref().unwrap()).unwrap(); // clean up afterwards
+        assert!(matches!(second_insertion, Err(Error::ProfileExists(_))));
+    }
+    #[test]
+    fn delete_rename_and_copy_report_missing_and_taken_names() {
+        let conn = create_connection();
+        initialize_db(&conn).unwrap();
+        let user = GenerationData::new(Some(14), Some(2), None, None, None, Some(String::from("original")));
+        let other = GenerationData::new(None, None, None, None, None, Some(String::from("other")));
+        insert_user_profile(&conn, &user).unwrap();
+        insert_user_profile(&conn, &other).unwrap();
+        let original = String::from("original");
+        let renamed = String::from("renamed");
+        let copied = String::from("copied");
+        let taken = String::from("other");
+        let missing = String::from("missing_profile");
+        assert!(matches!(rename_profile(&conn, &original, &taken), Err(Error::ProfileExists(x)) if x == "other"));
+        assert!(matches!(rename_profile(&conn, &missing, &renamed), Err(Error::ProfileNotFound(_))));
+        rename_profile(&conn, &original, &renamed).unwrap();
+        assert!(matches!(retrieve_profile_settings(&conn, &original), Err(Error::ProfileNotFound(_))));
+        assert_eq!(retrieve_profile_settings(&conn, &renamed).unwrap().minimum_length, 14);
+        assert!(matches!(copy_profile(&conn, &renamed, &taken), Err(Error::ProfileExists(_))));
+        assert!(matches!(copy_profile(&conn, &missing, &copied), Err(Error::ProfileNotFound(_))));
+        copy_profile(&conn, &renamed, &copied).unwrap();
+        let mut expected = user.clone();
+        expected.profile = Some(copied.clone());
+        assert_eq!(retrieve_profile_settings(&conn, &copied).unwrap(), expected);
+        delete_user_profile(&conn, &renamed).unwrap();
+        assert!(matches!(delete_user_profile(&conn, &renamed), Err(Error::ProfileNotFound(_))));
+        assert_eq!(profile_names(&conn).unwrap(), vec![taken, copied]);
+    }
+    #[test]
+    fn class_settings_round_trip() {
+        let conn = create_connection();
+        initialize_db(&conn).unwrap();
+        let mut user = GenerationData::new(Some(12), Some(2), Some(2), Some(1), Some(false), Some(String::from("ranged_profile")));
+        user.max_numbers = Some(4);
+        user.max_ucase = Some(1);
+        user.symbol_set = Some(String::from("!@#$%"));
+        user.exclude_ambiguous = true;
+        user.maximum_length = Some(20);
+        user.exact_length = Some(18);
+        user.min_entropy = Some(64.5);
+        user.word_count = Some(6);
+        user.separator = Separator::Symbol;
+        user.language = Language::French;
+        user.word_style = WordStyle::OneCaps;
+        user.leetspeak = true;
+        user.pronounceable = true;
+        user.site = Some(String::from("example.com"));
+        user.username = Some(String::from("alice"));
+        user.counter = 3;
+        user.token_format = Some(TokenFormat::Base32NoPad);
+        user.token_bytes = Some(20);
+        user.token_prefix = Some(String::from("sk_live_"));
+        user.token_checksum = true;
+        user.pin_length = Some(6);
+        user.pin_rules = PinRules { repeated: true, sequence: true, pattern: false, date: false, keypad: true };
+        user.pin_blocklist = Some(String::from("112233,445566"));
+        user.pattern = Some(String::from("Cvccvc-99-Cvccvc"));
+        user.word_list_path = Some(String::from("eff_large_wordlist.txt"));
+        user.word_list_hash = Some(String::from("9d2c8f3a"));
+        insert_user_profile(&conn, &user).unwrap();
+        let retrieved_profile = retrieve_profile_settings(&conn, user.profile.as_ref().unwrap()).unwrap();
+        assert_eq!(retrieved_profile, user);
+    }
+    #[test]
+    fn modify_single_setting_updates_one_column() {
+        let conn = create_connection();
+        initialize_db(&conn).unwrap();
+        let user = GenerationData::new(Some(8), None, None, None, None, Some(String::from("modified_profile")));
+        insert_user_profile(&conn, &user).unwrap();
+        let profile_name = user.profile.clone().unwrap();
+        modify_single_setting(&conn, &profile_name, SettingColumn::MinimumLength, &16).unwrap();
+        modify_single_setting(&conn, &profile_name, SettingColumn::Separator, &Separator::Space).unwrap();
+        let retrieved_profile = retrieve_profile_settings(&conn, &profile_name).unwrap();
+        assert_eq!(retrieved_profile.minimum_length, 16);
+        assert_eq!(retrieved_profile.separator, Separator::Space);
+        let missing = String::from("missing_profile");
+        assert!(matches!(modify_single_setting(&conn, &missing, SettingColumn::MinimumLength, &16), Err(Error::ProfileNotFound(_))));
+    }
+    #[test]
+    fn update_profile_reports_changed_columns() {
+        let conn = create_connection();
+        initialize_db(&conn).unwrap();
+        let user = GenerationData::new(Some(8), Some(1), None, None, None, Some(String::from("updated_profile")));
+        insert_user_profile(&conn, &user).unwrap();
+        let mut updated = user.clone();
+        updated.minimum_length = 16;
+        updated.max_numbers = Some(3);
+        updated.word_style = WordStyle::Title;
+        let changed = update_profile(&conn, user.profile.as_ref().unwrap(), &updated).unwrap();
+        assert_eq!(changed, vec![SettingColumn::MinimumLength, SettingColumn::MaxNumbers, SettingColumn::WordStyle]);
+        assert_eq!(retrieve_profile_settings(&conn, user.profile.as_ref().unwrap()).unwrap(), updated);
+        assert_eq!(update_profile(&conn, user.profile.as_ref().unwrap(), &updated).unwrap(), Vec::new());
+        let missing = String::from("missing_profile");
+        assert!(matches!(update_profile(&conn, &missing, &updated), Err(Error::ProfileNotFound(_))));
+    }
+    #[test]
+    #[cfg(unix)]
+    fn new_database_is_private_to_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+        let directory = std::env::temp_dir().join(format!("password_generator_private_{}", std::process::id()));
+        let path = directory.join("password-generator").join("profiles.db");
+        let conn = open_database(&path).unwrap();
+        initialize_db(&conn).unwrap();
+        let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(path.parent().unwrap()), 0o700);
+        drop(conn);
+        // reopening an existing database works and leaves it as it is
+        let conn = open_database(&path).unwrap();
+        assert!(profile_names(&conn).unwrap().is_empty());
+        drop(conn);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+    #[test]
+    fn every_setting_has_a_column() {
+        let conn = create_connection();
+        initialize_db(&conn).unwrap();
+        let statement = conn.prepare("SELECT * FROM password_settings;").unwrap();
+        let columns = statement.column_names();
+        for column in SettingColumn::ALL {
+            assert!(columns.contains(&column.as_str()), "{}", column.as_str());
+        }
+    }
 }
-
-
-
-
-
-
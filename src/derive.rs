@@ -0,0 +1,126 @@
+//! Deterministic, site-specific passwords (stateless mode). The same master secret, site,
+//! username and counter always give the same password, so nothing secret has to be stored.
+//!
+//! A memory-hard KDF turns the inputs into a 32-byte seed for a ChaCha20 stream, and the stream
+//! then drives the ordinary generator in place of the operating system's rng. The generator only
+//! samples through rejection-based `gen_range` and `choose`, so the mapping from derived bytes to
+//! characters is unbiased and every character class rule of `GenerationData` applies unchanged.
+//!
+//! Each algorithm is identified by a versioned name that is stored in profiles. Anything that
+//! changes the output for given inputs (KDF parameters, salt layout, the sampling done by the
+//! generator) needs a new version; the old one has to keep producing the old passwords. Settings
+//! that use a word list also depend on the list's contents, which `word_list_hash` pins down for
+//! word list files.
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use crate::generation_logic::GenerationError;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DerivationAlgorithm {
+    /// Argon2id with 19 MiB of memory, 2 passes and 1 lane (the OWASP baseline), whose 32-byte
+    /// output seeds ChaCha20
+    Argon2idChaCha20V1,
+}
+impl DerivationAlgorithm {
+    /// The algorithm used for new profiles
+    pub const LATEST: DerivationAlgorithm = DerivationAlgorithm::Argon2idChaCha20V1;
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DerivationAlgorithm::Argon2idChaCha20V1 => "argon2id-chacha20-v1",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<DerivationAlgorithm> {
+        match name {
+            "argon2id-chacha20-v1" => Some(DerivationAlgorithm::Argon2idChaCha20V1),
+            _ => None,
+        }
+    }
+}
+/// Salt for the KDF: the algorithm name followed by the length-prefixed site and username and the
+/// counter, so that no two sets of inputs share a salt. Sites are compared case-insensitively.
+fn derivation_salt(algorithm: DerivationAlgorithm, site: &str, username: &str, counter: u32) -> Vec<u8> {
+    let site = site.trim().to_lowercase();
+    let mut salt = Vec::from(algorithm.as_str().as_bytes());
+    for field in [site.as_bytes(), username.trim().as_bytes()] {
+        salt.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        salt.extend_from_slice(field);
+    }
+    salt.extend_from_slice(&counter.to_be_bytes());
+    salt
+}
+/// Derives the 32-byte seed for one site, username and counter from the master secret
+pub fn derive_seed(algorithm: DerivationAlgorithm, master_secret: &[u8], site: &str, username: &str, counter: u32) -> Result<[u8; 32], GenerationError> {
+    if master_secret.is_empty() {
+        return Err(GenerationError::Derivation(String::from("the master secret is empty")));
+    }
+    if site.trim().is_empty() {
+        return Err(GenerationError::Derivation(String::from("a site is required")));
+    }
+    let mut seed = [0u8; 32];
+    match algorithm {
+        DerivationAlgorithm::Argon2idChaCha20V1 => {
+            let params = Params::new(19 * 1024, 2, 1, Some(seed.len()))
+                .map_err(|x| GenerationError::Derivation(x.to_string()))?;
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(master_secret, &derivation_salt(algorithm, site, username, counter), &mut seed)
+                .map_err(|x| GenerationError::Derivation(x.to_string()))?;
+        }
+    }
+    Ok(seed)
+}
+/// The rng `GenerationData::derive_password` generates from
+pub fn derived_rng(algorithm: DerivationAlgorithm, master_secret: &[u8], site: &str, username: &str, counter: u32) -> Result<ChaCha20Rng, GenerationError> {
+    Ok(ChaCha20Rng::from_seed(derive_seed(algorithm, master_secret, site, username, counter)?))
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        derive_seed,
+        DerivationAlgorithm,
+    };
+    use crate::generation_logic::{
+        GenerationData,
+        GenerationError,
+    };
+    use data_encoding::HEXLOWER;
+
+    fn site_settings(site: &str, username: &str, counter: u32) -> GenerationData {
+        let mut settings = GenerationData::new(Some(12), Some(2), Some(1), Some(1), Some(false), None);
+        settings.site = Some(String::from(site));
+        settings.username = Some(String::from(username));
+        settings.counter = counter;
+        settings
+    }
+    // test vectors for argon2id-chacha20-v1; these must never change
+    #[test]
+    fn seed_matches_test_vector() {
+        let seed = derive_seed(DerivationAlgorithm::Argon2idChaCha20V1, b"correct horse battery staple", "example.com", "alice", 1).unwrap();
+        assert_eq!(HEXLOWER.encode(&seed), "a064d39fcecb6aca2b4020ecd60581872bcd9d592f2e976db8b73aeff1bc328e");
+    }
+    #[test]
+    fn passwords_match_test_vectors() {
+        let vectors = [
+            ("example.com", "alice", 1, "tn*iciaDsbgu87jg"),
+            ("example.com", "alice", 2, "rmqQxm59wivg#pju"),
+            ("github.com", "bob", 1, "nicrCmo4b4gas_pn"),
+        ];
+        for (site, username, counter, expected) in vectors {
+            let settings = site_settings(site, username, counter);
+            assert_eq!(settings.derive_password(b"correct horse battery staple").unwrap(), expected);
+        }
+    }
+    #[test]
+    fn site_is_case_insensitive() {
+        let settings = site_settings(" Example.COM", "alice", 1);
+        assert_eq!(settings.derive_password(b"correct horse battery staple").unwrap(), "tn*iciaDsbgu87jg");
+    }
+    #[test]
+    fn missing_inputs_return_error() {
+        let settings = site_settings("example.com", "alice", 1);
+        assert!(matches!(settings.derive_password(b""), Err(GenerationError::Derivation(_))));
+        let mut no_site = settings.clone();
+        no_site.site = None;
+        assert!(matches!(no_site.derive_password(b"secret"), Err(GenerationError::Derivation(_))));
+    }
+}
@@ -0,0 +1,200 @@
+//! Entropy estimates for generation settings. All figures are in bits and describe the process
+//! that produces a password, not any one password, so they only depend on `GenerationData`.
+//!
+//! Lowercase letters, digits, uppercase letters and symbols are disjoint alphabets, so a password
+//! made of `n` base characters plus `c1`, `c2`, `c3` class characters carries
+//!
+//! ```text
+//! base bits + log2((n + c1 + c2 + c3)! / (n! c1! c2! c3!)) + sum of ck * log2(alphabet k)
+//! ```
+//!
+//! where the middle term accounts for the random placement of the class characters. Passphrase
+//! words are drawn independently, so each contributes exactly `log2(word list size)`. When a class
+//! has a count range only its minimum is counted, which keeps the estimate on the safe side.
+//!
+//! Pronounceable mode alternates consonants and vowels starting with a consonant, so a base of
+//! `n` letters carries `ceil(n / 2) * log2(consonants) + floor(n / 2) * log2(vowels)` bits.
+//!
+//! Tokens carry 8 bits per random byte, or 122 bits for a version 4 UUID.
+//!
+//! PINs are `log2` of the number of PINs the rules accept; see `pin::pin_entropy`.
+//!
+//! Patterns are exact: each class token adds `log2(alphabet size)` and each word token
+//! `log2(word list size)`.
+//!
+//! Word styles add one bit per word for random case and `log2(word count)` for the position of
+//! the all-caps word; title and camel case are fixed, and so is leetspeak, so they add nothing.
+//! In passphrase mode the uppercase letters a style always produces are subtracted from the
+//! uppercase characters that get inserted, as the generator does.
+use crate::pin;
+use crate::generation_logic::{
+    class_total,
+    is_ambiguous,
+    pronounceable_alphabets,
+    words_within_entropy,
+    GenerationData,
+    GenerationError,
+    Separator,
+    WordStyle,
+};
+
+/// Bits of entropy for the given settings, exactly as `generate_password` would use them.
+/// Character and passphrase modes are exact. Dictionary mode with a maximum or exact length counts
+/// only the words the generator can draw within those bounds; without one it assumes the number of
+/// words is the base length divided by the average word length, rounded up.
+pub fn settings_entropy(settings: &GenerationData) -> Result<f64, GenerationError> {
+    if let Some(format) = settings.token_format {
+        return Ok(format.entropy_bits(settings.checked_token_bytes(format)?));
+    }
+    if let Some(pin_length) = settings.pin_length {
+        settings.check_length_limits()?;
+        let digits = pin::pin_digits(settings.exclude_ambiguous);
+        return pin::pin_entropy(pin_length, &digits, &settings.pin_rules, &settings.pin_blocklist_set()?);
+    }
+    if let Some(pattern) = &settings.pattern {
+        let (pattern, word_list) = settings.pattern_plan(pattern)?;
+        let list_size = word_list.as_ref().map(|list| pattern.usable_words(list, settings.exclude_ambiguous).len()).unwrap_or(0);
+        return Ok(pattern.entropy_bits(&settings.symbol_alphabet()?, list_size, settings.exclude_ambiguous));
+    }
+    let mut class_counts = [settings.min_numbers, settings.min_special, settings.min_ucase];
+    let class_total = class_total(&class_counts)?;
+    let digit_alphabet = alphabet_size('0', '9', settings.exclude_ambiguous);
+    let symbol_alphabet = settings.symbol_alphabet()?.len();
+    let (base_bits, base_length) = match settings.word_count {
+        Some(word_count) => {
+            settings.check_length_limits()?;
+            let (list_size, average_length) = word_list_stats(settings)?;
+            let gaps = word_count.saturating_sub(1);
+            let separator_bits = match settings.separator {
+                Separator::Digit => gaps as f64 * (digit_alphabet as f64).log2(),
+                Separator::Symbol => gaps as f64 * (symbol_alphabet as f64).log2(),
+                _ => 0.0,
+            };
+            let separator_length = if settings.separator == Separator::None {0} else {gaps};
+            let length = (word_count as f64 * average_length) as usize + separator_length;
+            class_counts[2] = class_counts[2].saturating_sub(settings.word_style.guaranteed_uppercase(word_count));
+            let word_bits = word_count as f64 * (list_size as f64).log2() + word_style_bits(settings.word_style, word_count as f64);
+            (word_bits + separator_bits, length)
+        }
+        None if settings.pronounceable => {
+            let (base_length, _) = settings.base_length_bounds(class_total)?;
+            let (consonants, vowels) = pronounceable_alphabets(settings.exclude_ambiguous);
+            let consonant_count = base_length.div_ceil(2);
+            let vowel_count = base_length / 2;
+            let bits = consonant_count as f64 * (consonants.len() as f64).log2()
+                + vowel_count as f64 * (vowels.len() as f64).log2();
+            (bits, base_length)
+        }
+        None if settings.use_words => {
+            let (base_length, base_upper) = settings.base_length_bounds(class_total)?;
+            match base_upper {
+                Some(base_upper) => {
+                    // only words that fit the bounds can be drawn
+                    let word_list = settings.load_word_list()?;
+                    let words = settings.usable_words(&word_list);
+                    if words.is_empty() {
+                        return Err(GenerationError::InvalidWordList(String::from("no usable words are left in the list")));
+                    }
+                    let (word_bits, word_count) = words_within_entropy(&words, base_length, base_upper)?;
+                    (word_bits + word_style_bits(settings.word_style, word_count), base_length)
+                }
+                None => {
+                    let (list_size, average_length) = word_list_stats(settings)?;
+                    let word_count = (base_length as f64 / average_length).ceil();
+                    (word_count * (list_size as f64).log2() + word_style_bits(settings.word_style, word_count), base_length)
+                }
+            }
+        }
+        None => {
+            let (base_length, _) = settings.base_length_bounds(class_total)?;
+            let letter_alphabet = alphabet_size('a', 'z', settings.exclude_ambiguous);
+            (base_length as f64 * (letter_alphabet as f64).log2(), base_length)
+        }
+    };
+    let class_alphabets = [
+        digit_alphabet,
+        symbol_alphabet,
+        alphabet_size('A', 'Z', settings.exclude_ambiguous),
+    ];
+    let class_bits: f64 = class_counts.iter()
+        .zip(class_alphabets.iter())
+        .map(|(&count, &size)| count as f64 * (size as f64).log2())
+        .sum();
+    let class_total: usize = class_counts.iter().sum();
+    let placement_bits = log2_factorial(base_length + class_total)
+        - log2_factorial(base_length)
+        - class_counts.iter().map(|&count| log2_factorial(count)).sum::<f64>();
+    Ok(base_bits + class_bits + placement_bits)
+}
+/// Bits added by the random choices of a word style over `word_count` words
+fn word_style_bits(word_style: WordStyle, word_count: f64) -> f64 {
+    match word_style {
+        WordStyle::RandomCase => word_count,
+        WordStyle::OneCaps if word_count > 1.0 => word_count.log2(),
+        _ => 0.0,
+    }
+}
+/// Number of characters between `start` and `end` that the generator can produce
+pub fn alphabet_size(start: char, end: char, exclude_ambiguous: bool) -> usize {
+    (start..=end).filter(|&ch| !(exclude_ambiguous && is_ambiguous(ch))).count()
+}
+/// Size and average word length of the word list used by the settings
+fn word_list_stats(settings: &GenerationData) -> Result<(usize, f64), GenerationError> {
+    let word_list = settings.load_word_list()?;
+    let words = settings.usable_words(&word_list);
+    if words.is_empty() {
+        return Err(GenerationError::InvalidWordList(String::from("no usable words are left in the list")));
+    }
+    let total_length: usize = words.iter().map(|word| word.chars().count()).sum();
+    Ok((words.len(), total_length as f64 / words.len() as f64))
+}
+fn log2_factorial(n: usize) -> f64 {
+    (2..=n).map(|i| (i as f64).log2()).sum()
+}
+#[cfg(test)]
+mod tests {
+    use super::settings_entropy;
+    use crate::generation_logic::GenerationData;
+
+    #[test]
+    fn character_mode_entropy_is_exact() {
+        let letters_only = GenerationData::new(Some(10), Some(0), Some(0), Some(0), Some(false), None);
+        assert!((settings_entropy(&letters_only).unwrap() - 10.0 * 26f64.log2()).abs() < 1e-9);
+        // one digit adds its own 10 choices plus 11 possible positions
+        let with_digit = GenerationData::new(Some(10), Some(1), Some(0), Some(0), Some(false), None);
+        let expected = 10.0 * 26f64.log2() + 10f64.log2() + 11f64.log2();
+        assert!((settings_entropy(&with_digit).unwrap() - expected).abs() < 1e-9);
+    }
+    #[test]
+    fn pronounceable_entropy_follows_syllable_model() {
+        let mut settings = GenerationData::new(Some(9), Some(0), Some(0), Some(0), Some(false), None);
+        settings.pronounceable = true;
+        let expected = 5.0 * 17f64.log2() + 4.0 * 5f64.log2();
+        assert!((settings_entropy(&settings).unwrap() - expected).abs() < 1e-9);
+        // `l` is the only ambiguous letter in the model
+        settings.exclude_ambiguous = true;
+        let expected = 5.0 * 16f64.log2() + 4.0 * 5f64.log2();
+        assert!((settings_entropy(&settings).unwrap() - expected).abs() < 1e-9);
+    }
+    #[test]
+    fn tighter_dictionary_bounds_lower_entropy() {
+        let exact = |length: usize| {
+            let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), Some(true), None);
+            settings.exact_length = Some(length);
+            settings_entropy(&settings).unwrap()
+        };
+        assert!(exact(4) < exact(6));
+        assert!(exact(6) < exact(12));
+        let mut unbounded = GenerationData::new(Some(6), Some(0), Some(0), Some(0), Some(true), None);
+        let full = settings_entropy(&unbounded).unwrap();
+        unbounded.maximum_length = Some(6);
+        assert!(settings_entropy(&unbounded).unwrap() < full);
+    }
+    #[test]
+    fn excluding_ambiguous_characters_lowers_entropy() {
+        let mut settings = GenerationData::new(Some(12), Some(2), Some(2), Some(2), Some(false), None);
+        let full = settings_entropy(&settings).unwrap();
+        settings.exclude_ambiguous = true;
+        assert!(settings_entropy(&settings).unwrap() < full);
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use crate::generation_logic::GenerationError;
+
+/// Crate-wide error type. Every fallible operation in the library and the command line tool
+/// reports one of these, and `main` turns it into a process exit code via `Error::exit_code`.
+#[derive(Debug)]
+pub enum Error {
+    /// The profile database could not be opened, read or written
+    Database(rusqlite::Error),
+    /// A command line option or value could not be understood
+    Parse(String),
+    /// The generation settings cannot be satisfied
+    Policy(GenerationError),
+    /// No profile with the given name exists
+    ProfileNotFound(String),
+    /// A profile with the given name already exists
+    ProfileExists(String),
+    /// The profile database could not be backed up or has a schema newer than this build
+    Migration(String),
+    /// The profile database file or its directory could not be created, or has nowhere to go
+    Storage(String),
+    /// A file or stdin could not be read, or a file could not be written
+    Io(String),
+    /// The user didn't confirm a destructive command when asked
+    NotConfirmed(String),
+}
+impl Error {
+    /// Exit code used by the command line tool for this error. Success is always 0.
+    ///
+    /// | code | meaning |
+    /// |------|---------|
+    /// | 2    | invalid command line arguments |
+    /// | 3    | database error |
+    /// | 4    | profile not found |
+    /// | 5    | generation settings cannot be satisfied |
+    /// | 6    | profile already exists |
+    /// | 7    | a file or stdin could not be read or written |
+    /// | 8    | a confirmation prompt was declined |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Parse(_) => 2,
+            Error::Database(_) | Error::Migration(_) | Error::Storage(_) => 3,
+            Error::ProfileNotFound(_) => 4,
+            Error::Policy(_) => 5,
+            Error::ProfileExists(_) => 6,
+            Error::Io(_) => 7,
+            Error::NotConfirmed(_) => 8,
+        }
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Database(x) => write!(f, "database error: {}", x),
+            Error::Parse(x) => write!(f, "invalid arguments: {}", x),
+            Error::Policy(x) => write!(f, "could not generate password: {}", x),
+            Error::ProfileNotFound(x) => {
+                write!(f, "no profile named '{}' exists. You can use the 'profile list' command to view a list of profiles.", x)
+            }
+            Error::Migration(x) | Error::Storage(x) => write!(f, "database error: {}", x),
+            Error::Io(x) => write!(f, "input/output error: {}", x),
+            Error::ProfileExists(x) => write!(f, "a profile named '{}' already exists", x),
+            Error::NotConfirmed(x) => write!(f, "{} was not confirmed; pass --yes to skip the prompt", x),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(x) => Some(x),
+            Error::Policy(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Database(err)
+    }
+}
+impl From<GenerationError> for Error {
+    fn from(err: GenerationError) -> Error {
+        Error::Policy(err)
+    }
+}
@@ -1,112 +1,1442 @@
-use random_word;
 use std::collections::HashSet;
-use std::process::exit;
-use crate::database;
-use rand;
-use rand::Rng;
-pub fn generate_random_char(start: char, end:char) -> char {
-    let mut rng = rand::thread_rng();
-    let random_char : char = rng.gen_range(start..=end);
-    random_char
+use std::fmt;
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use rand::{CryptoRng, Rng, RngCore};
+use crate::derive;
+use crate::derive::DerivationAlgorithm;
+use crate::entropy;
+use crate::pattern::Pattern;
+use crate::pin;
+use crate::pin::PinRules;
+use crate::token;
+use crate::token::TokenFormat;
+use crate::word_list::WordList;
+// All randomness is drawn from a caller-provided `RngCore + CryptoRng`, so a non-cryptographic
+// generator can't be passed in by accident. `GenerationData::generate_password` uses `OsRng`.
+/// Characters that are easily confused with one another when read aloud or off paper
+pub const AMBIGUOUS_CHARS: &str = "l1IO0|";
+pub fn is_ambiguous(ch: char) -> bool {
+    AMBIGUOUS_CHARS.contains(ch)
 }
-pub fn generate_lcase_char() -> char {
-    generate_random_char('a', 'z')
+/// Picks a random character between `start` and `end`. Ambiguous characters are rejected and
+/// redrawn when `exclude_ambiguous` is set, which keeps the remaining characters equally likely.
+pub fn generate_random_char<R: RngCore + CryptoRng>(rng: &mut R, start: char, end:char, exclude_ambiguous: bool) -> char {
+    loop {
+        let random_char: char = rng.gen_range(start..=end);
+        if !(exclude_ambiguous && is_ambiguous(random_char)) {
+            return random_char;
+        }
+    }
+}
+pub fn generate_lcase_char<R: RngCore + CryptoRng>(rng: &mut R, exclude_ambiguous: bool) -> char {
+    generate_random_char(rng, 'a', 'z', exclude_ambiguous)
+}
+pub fn generate_ucase_char<R: RngCore + CryptoRng>(rng: &mut R, exclude_ambiguous: bool) -> char {
+    generate_random_char(rng, 'A', 'Z', exclude_ambiguous)
+}
+pub fn generate_numeric_char<R: RngCore + CryptoRng>(rng: &mut R, exclude_ambiguous: bool) -> char {
+    generate_random_char(rng, '0', '9', exclude_ambiguous)
+}
+/// The OWASP list of password special characters, minus the space
+pub const DEFAULT_SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+/// Picks a random character from `symbols`, which must not be empty
+pub fn generate_special_char<R: RngCore + CryptoRng>(rng: &mut R, symbols: &[char]) -> char {
+    *symbols.choose(rng).expect("symbol set is empty")
+}
+/// Checks a custom symbol set and returns its distinct characters. Letters, digits and whitespace
+/// are rejected so that a "special" character always satisfies a symbol requirement.
+pub fn parse_symbol_set(symbols: &str) -> Result<Vec<char>, GenerationError> {
+    let mut symbol_chars: Vec<char> = Vec::new();
+    for ch in symbols.chars() {
+        if ch.is_alphanumeric() || ch.is_whitespace() || ch.is_control() {
+            return Err(GenerationError::InvalidSymbolSet(format!("'{}' is not a symbol", ch)));
+        }
+        if !symbol_chars.contains(&ch) {
+            symbol_chars.push(ch);
+        }
+    }
+    if symbol_chars.is_empty() {
+        return Err(GenerationError::InvalidSymbolSet(String::from("the symbol set is empty")));
+    }
+    Ok(symbol_chars)
+}
+/// Picks a random word from `words` using the provided rng rather than `random_word::gen`,
+/// which always uses the thread-local rng
+pub fn generate_word<'a, R: RngCore + CryptoRng>(rng: &mut R, words: &[&'a str]) -> &'a str {
+    words.choose(rng).expect("word list is empty")
+}
+/// Consonants used by pronounceable mode. Letters that are hard to say in isolation (`c`, `q`,
+/// `x`, `y`) are left out.
+pub const PRONOUNCEABLE_CONSONANTS: &str = "bdfghjklmnprstvwz";
+pub const PRONOUNCEABLE_VOWELS: &str = "aeiou";
+/// The consonants and vowels pronounceable mode draws from, after removing ambiguous ones if
+/// requested
+pub fn pronounceable_alphabets(exclude_ambiguous: bool) -> (Vec<char>, Vec<char>) {
+    let unambiguous = |ch: &char| !(exclude_ambiguous && is_ambiguous(*ch));
+    (PRONOUNCEABLE_CONSONANTS.chars().filter(unambiguous).collect(),
+     PRONOUNCEABLE_VOWELS.chars().filter(unambiguous).collect())
+}
+/// What goes between the words of a passphrase
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Separator {
+    None,
+    Space,
+    Hyphen,
+    /// A random digit for each gap
+    Digit,
+    /// A random symbol from the symbol set for each gap
+    Symbol,
+}
+impl Separator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Separator::None => "none",
+            Separator::Space => "space",
+            Separator::Hyphen => "hyphen",
+            Separator::Digit => "digit",
+            Separator::Symbol => "symbol",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Separator> {
+        match name {
+            "none" => Some(Separator::None),
+            "space" => Some(Separator::Space),
+            "hyphen" => Some(Separator::Hyphen),
+            "digit" => Some(Separator::Digit),
+            "symbol" => Some(Separator::Symbol),
+            _ => None,
+        }
+    }
+}
+/// How the letters of dictionary and passphrase words are capitalized
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WordStyle {
+    /// Words are used as they appear in the list
+    Lower,
+    /// The first letter of every word is uppercase
+    Title,
+    /// Each word is independently left as is or put in all caps
+    RandomCase,
+    /// Title case for every word but the first
+    Camel,
+    /// One randomly chosen word is put in all caps
+    OneCaps,
+}
+impl WordStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WordStyle::Lower => "lower",
+            WordStyle::Title => "title",
+            WordStyle::RandomCase => "random",
+            WordStyle::Camel => "camel",
+            WordStyle::OneCaps => "one_caps",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<WordStyle> {
+        match name {
+            "lower" => Some(WordStyle::Lower),
+            "title" => Some(WordStyle::Title),
+            "random" => Some(WordStyle::RandomCase),
+            "camel" => Some(WordStyle::Camel),
+            "one_caps" => Some(WordStyle::OneCaps),
+            _ => None,
+        }
+    }
+    /// Number of uppercase letters the style always produces in a phrase of `word_count` words
+    pub fn guaranteed_uppercase(&self, word_count: usize) -> usize {
+        match self {
+            WordStyle::Lower | WordStyle::RandomCase => 0,
+            WordStyle::Title => word_count,
+            WordStyle::Camel => word_count.saturating_sub(1),
+            WordStyle::OneCaps => word_count.min(1),
+        }
+    }
+    /// Whether the style can put every letter of a word in uppercase, rather than just the first
+    fn uppercases_whole_words(&self) -> bool {
+        matches!(self, WordStyle::RandomCase | WordStyle::OneCaps)
+    }
 }
-pub fn generate_ucase_char() -> char {
-    generate_random_char('A', 'Z')
+/// Uppercase form of `ch`, keeping `ch` when the uppercase form is more than one character (such
+/// as `ß`) so that styling never changes the length of a word
+fn uppercase_char(ch: char) -> char {
+    let mut uppercase = ch.to_uppercase();
+    match (uppercase.next(), uppercase.next()) {
+        (Some(upper), None) => upper,
+        _ => ch,
+    }
 }
-pub fn generate_numeric_char() -> char {
-    generate_random_char('0', '9')
+/// Leetspeak replacement for a lowercase letter. `i` and `o` are only replaced when ambiguous
+/// characters are allowed, since their replacements are `1` and `0`.
+fn leet_char(ch: char, exclude_ambiguous: bool) -> char {
+    match ch {
+        'a' => '4',
+        'e' => '3',
+        's' => '5',
+        't' => '7',
+        'i' if !exclude_ambiguous => '1',
+        'o' if !exclude_ambiguous => '0',
+        _ => ch,
+    }
 }
-pub fn generate_special_char() -> char {
-    generate_random_char('!', '~')
+/// Language of the built-in word list. Languages other than English are only available when the
+/// crate feature of the same code is enabled, e.g. `--features de`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Language {
+    English,
+    German,
+    Spanish,
+    French,
+    Chinese,
 }
+impl Language {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::Spanish => "es",
+            Language::French => "fr",
+            Language::Chinese => "zh",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "en" => Some(Language::English),
+            "de" => Some(Language::German),
+            "es" => Some(Language::Spanish),
+            "fr" => Some(Language::French),
+            "zh" => Some(Language::Chinese),
+            _ => None,
+        }
+    }
+}
+/// Reasons a password could not be generated from a given set of settings.
 #[derive(Debug, PartialEq)]
+pub enum GenerationError {
+    /// The settings ask for a zero-length password with no extra character classes
+    EmptyPassword,
+    /// The password can't be grown to the requested entropy within the length constraints
+    EntropyTooLow { required: f64, achievable: f64 },
+    /// The word list file could not be read or holds no usable words
+    InvalidWordList(String),
+    /// A character class has a maximum count below its minimum count
+    InvalidCountRange { class: &'static str, minimum: usize, maximum: usize },
+    /// The custom symbol set is empty or contains non-symbol characters
+    InvalidSymbolSet(String),
+    /// The pattern contains an unknown token or a malformed repetition count
+    InvalidPattern(String),
+    /// The PIN block list is malformed, or the rules leave no acceptable PIN
+    InvalidPin(String),
+    /// The token prefix contains characters that don't belong in a token
+    InvalidToken(String),
+    /// A deterministic password could not be derived, e.g. because the master secret is empty
+    Derivation(String),
+    /// The entropy target is not a finite number of bits between 0 and `MAX_MIN_ENTROPY`
+    InvalidMinEntropy(f64),
+    /// The settings allow too few different passwords for a batch of distinct ones
+    NotEnoughDistinct { requested: usize, generated: usize },
+    /// More passwords were requested in one batch than `MAX_BATCH_SIZE`
+    BatchTooLarge(usize),
+    /// The word list for this language was not compiled in
+    UnsupportedLanguage(Language),
+    /// No password can satisfy the length constraints together with the required characters
+    UnsatisfiableLength(String),
+}
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerationError::EmptyPassword => {
+                write!(f, "the provided settings would produce an empty password")
+            }
+            GenerationError::EntropyTooLow { required, achievable } => {
+                write!(f, "{:.1} bits of entropy were requested but the length constraints allow at most {:.1}", required, achievable)
+            }
+            GenerationError::InvalidCountRange { class, minimum, maximum } => {
+                write!(f, "the maximum number of {} ({}) is below the minimum ({})", class, maximum, minimum)
+            }
+            GenerationError::InvalidWordList(reason) => {
+                write!(f, "invalid word list: {}", reason)
+            }
+            GenerationError::InvalidSymbolSet(reason) => {
+                write!(f, "invalid symbol set: {}", reason)
+            }
+            GenerationError::InvalidPattern(reason) => {
+                write!(f, "invalid pattern: {}", reason)
+            }
+            GenerationError::InvalidPin(reason) => {
+                write!(f, "invalid PIN settings: {}", reason)
+            }
+            GenerationError::InvalidToken(reason) => {
+                write!(f, "invalid token settings: {}", reason)
+            }
+            GenerationError::Derivation(reason) => {
+                write!(f, "could not derive password: {}", reason)
+            }
+            GenerationError::InvalidMinEntropy(bits) => {
+                write!(f, "the entropy target {} is not a number of bits between 0 and {}", bits, MAX_MIN_ENTROPY)
+            }
+            GenerationError::NotEnoughDistinct { requested, generated } => {
+                write!(f, "{} distinct passwords were requested but the settings only produced {}", requested, generated)
+            }
+            GenerationError::BatchTooLarge(count) => {
+                write!(f, "{} passwords were requested but at most {} can be generated at once", count, MAX_BATCH_SIZE)
+            }
+            GenerationError::UnsupportedLanguage(language) => {
+                write!(f, "the '{}' word list is not available; rebuild with `--features {}`", language.as_str(), language.as_str())
+            }
+            GenerationError::UnsatisfiableLength(reason) => {
+                write!(f, "length constraints cannot be met: {}", reason)
+            }
+        }
+    }
+}
+impl std::error::Error for GenerationError {}
+/// Settings for a single password. Each character class has a minimum count and an optional
+/// maximum; when a maximum is set the number of characters drawn from that class is picked
+/// uniformly between the two, otherwise exactly the minimum is used.
+///
+/// `minimum_length` is the length of the letters or words the class characters are added to,
+/// while `maximum_length` and `exact_length` bound the finished password. `exact_length` takes
+/// precedence over `minimum_length`.
+///
+/// When `min_entropy` is set, `minimum_length` is raised as far as needed for the estimate from
+/// `entropy_bits` to reach it.
+///
+/// With `pronounceable` set, the letters part alternates consonants and vowels (`tobakivu`)
+/// instead of being fully random or made of dictionary words. It takes precedence over `use_words`.
+///
+/// `site`, `username`, `counter` and `derivation` are the inputs of `derive_password`, which
+/// computes a password from a master secret instead of drawing it at random.
+///
+/// Setting `token_format` generates a token such as an API key from `token_bytes` random bytes
+/// instead of a password (see the `token` module); every other option is ignored, including
+/// `pin_length` and `pattern`.
+///
+/// Setting `pin_length` generates a numeric PIN of that many digits, rejecting weak PINs according
+/// to `pin_rules` and `pin_blocklist` (see the `pin` module). Unless `token_format` is set, it
+/// takes precedence over all other options except `exclude_ambiguous`.
+///
+/// A `pattern` replaces all of the length and character class options with a fixed shape; see
+/// the `pattern` module for the syntax. Token and PIN modes take precedence over it.
+///
+/// Setting `word_count` switches to passphrase mode: exactly that many words, drawn independently
+/// (diceware style) and joined by `separator`. The length options don't apply in this mode.
+#[derive(Debug, PartialEq, Clone)]
 pub struct GenerationData {
     pub minimum_length: usize,
-    pub include_numbers: bool,
-    pub include_special: bool,
-    pub include_ucase: bool,
+    pub maximum_length: Option<usize>,
+    pub exact_length: Option<usize>,
+    pub min_numbers: usize,
+    pub max_numbers: Option<usize>,
+    pub min_special: usize,
+    pub max_special: Option<usize>,
+    pub min_ucase: usize,
+    pub max_ucase: Option<usize>,
+    /// Characters special characters are drawn from; `None` means `DEFAULT_SYMBOLS`
+    pub symbol_set: Option<String>,
+    /// Leave out characters listed in `AMBIGUOUS_CHARS`, and words containing them
+    pub exclude_ambiguous: bool,
+    /// Minimum bits of entropy the password must have
+    pub min_entropy: Option<f64>,
     pub use_words: bool,
+    /// Build the letters part from consonant-vowel syllables rather than random letters or words
+    pub pronounceable: bool,
+    /// Number of words in passphrase mode
+    pub word_count: Option<usize>,
+    pub separator: Separator,
+    /// Site a deterministic password is derived for
+    pub site: Option<String>,
+    /// Username a deterministic password is derived for
+    pub username: Option<String>,
+    /// Bumped to get a new deterministic password for the same site and username
+    pub counter: u32,
+    /// Versioned algorithm `derive_password` uses
+    pub derivation: DerivationAlgorithm,
+    /// Encoding of the random bytes in token mode, which wins over PIN and pattern modes
+    pub token_format: Option<TokenFormat>,
+    /// Number of random bytes in token mode; `None` means `token::DEFAULT_TOKEN_BYTES`
+    pub token_bytes: Option<usize>,
+    /// Text put in front of tokens, such as `sk_live_`
+    pub token_prefix: Option<String>,
+    /// Append a checksum to tokens
+    pub token_checksum: bool,
+    /// Number of digits in PIN mode; ignored when `token_format` is set
+    pub pin_length: Option<usize>,
+    /// Weak-PIN rules enforced in PIN mode
+    pub pin_rules: PinRules,
+    /// Comma-separated PINs that are rejected on top of the rules
+    pub pin_blocklist: Option<String>,
+    /// Shape of the password, such as `Cvccvc-99-Cvccvc`, used instead of the other options
+    pub pattern: Option<String>,
+    /// Capitalization applied to each word in dictionary and passphrase modes
+    pub word_style: WordStyle,
+    /// Replace some lowercase letters of words with look-alike digits (`a` to `4`, `e` to `3`, ...)
+    pub leetspeak: bool,
+    /// Language of the built-in word list, used when `word_list_path` is `None`
+    pub language: Language,
+    /// Word list file for dictionary and passphrase modes; `None` uses the built-in list
+    pub word_list_path: Option<String>,
+    /// SHA-256 hash the word list file must still have, recorded when a profile is saved
+    pub word_list_hash: Option<String>,
     pub profile: Option<String>,
-    pub overwrite: bool,
 
 }
 impl GenerationData {
+    /// Creates settings with the given minimum counts per class; maximums start out unset.
     pub fn new(minimum_length: Option<usize>, 
-               include_numbers: Option<bool>, 
-               include_special: Option<bool>, 
-               include_ucase: Option<bool>, 
+               min_numbers: Option<usize>, 
+               min_special: Option<usize>, 
+               min_ucase: Option<usize>, 
                use_words: Option<bool>,
-               profile: Option<String>,
-               overwrite: Option<bool>
+               profile: Option<String>
                )-> GenerationData{
-        let minimum_length = if let Some(min_length) = minimum_length{min_length} else {8};
-        let include_numbers = if let Some(include_nums) = include_numbers{include_nums} else {false};
-        let include_special = if let Some(include_spec) = include_special{include_spec} else {false};
-        let include_ucase = if let Some(include_u) = include_ucase{include_u} else {false};
-        let use_words = if let Some(use_wrds) = use_words{use_wrds} else {true};
-        // let profile = if let Some(prof) = profile{} else None;
-        let overwrite = if let Some(overw) = overwrite{overw} else {false};
+        let minimum_length = minimum_length.unwrap_or(8);
+        let min_numbers = min_numbers.unwrap_or(0);
+        let min_special = min_special.unwrap_or(0);
+        let min_ucase = min_ucase.unwrap_or(0);
+        let use_words = use_words.unwrap_or(true);
         GenerationData{
             minimum_length,
-            include_numbers,
-            include_special,
-            include_ucase,
+            maximum_length: None,
+            exact_length: None,
+            min_numbers,
+            max_numbers: None,
+            min_special,
+            max_special: None,
+            min_ucase,
+            max_ucase: None,
+            symbol_set: None,
+            exclude_ambiguous: false,
+            min_entropy: None,
             use_words,
-            profile,
-            overwrite
+            pronounceable: false,
+            word_count: None,
+            separator: Separator::Hyphen,
+            site: None,
+            username: None,
+            counter: 1,
+            derivation: DerivationAlgorithm::LATEST,
+            token_format: None,
+            token_bytes: None,
+            token_prefix: None,
+            token_checksum: false,
+            pin_length: None,
+            pin_rules: PinRules::default(),
+            pin_blocklist: None,
+            pattern: None,
+            word_style: WordStyle::Lower,
+            leetspeak: false,
+            language: Language::English,
+            word_list_path: None,
+            word_list_hash: None,
+            profile
         }
     }
-    pub fn generate_password(&mut self) -> String{
-        let mut password = String::new();
-        let mut used_words = HashSet::new();
-        if let Some(profile_name) = &self.profile {
-            println!("Generating password based on saved profile: {}", &profile_name);
-            let db_conn = database::create_connection();
-            let user_profile = database::retrieve_profile_settings(&db_conn, profile_name);
-            match user_profile {
-                Some(profile) => {
-                    println!("Profile settings retrieved successfully. Preparing to generate password...");
-                    self.minimum_length = profile.minimum_length;
-                    self.include_numbers = profile.include_numbers;
-                    self.include_special = profile.include_special;
-                    self.include_ucase = profile.include_ucase;
-                    self.use_words = profile.use_words;
-                    self.overwrite = profile.overwrite;
+    /// Rejects minimum and exact lengths, word counts and PIN lengths above `MAX_PASSWORD_LENGTH`,
+    /// which would only exhaust memory. Maximum lengths may be anything, since they only cap the
+    /// length.
+    pub(crate) fn check_length_limits(&self) -> Result<(), GenerationError> {
+        let limits = [
+            ("minimum length", Some(self.minimum_length)),
+            ("exact length", self.exact_length),
+            ("word count", self.word_count),
+            ("PIN length", self.pin_length),
+        ];
+        for (name, value) in limits {
+            if let Some(value) = value.filter(|&value| value > MAX_PASSWORD_LENGTH) {
+                return Err(GenerationError::UnsatisfiableLength(
+                    format!("the {} {} is above the supported maximum of {}", name, value, MAX_PASSWORD_LENGTH)));
+            }
+        }
+        Ok(())
+    }
+    /// Works out how long the letters or words part of the password may be once `class_total`
+    /// class characters are added. The upper bound is `None` when only a minimum applies.
+    pub(crate) fn base_length_bounds(&self, class_total: usize) -> Result<(usize, Option<usize>), GenerationError> {
+        self.check_length_limits()?;
+        if let Some(exact_length) = self.exact_length {
+            if let Some(maximum_length) = self.maximum_length {
+                if exact_length > maximum_length {
+                    return Err(GenerationError::UnsatisfiableLength(
+                        format!("the exact length {} is above the maximum length {}", exact_length, maximum_length)));
                 }
-                None => {
-                    println!("Failed to retrieve profile settings. Double-check the profile name.");
-                    println!("You can use the '--list_profiles' option to view a list of profiles.");
-                    exit(1);
+            }
+            if class_total > exact_length {
+                return Err(GenerationError::UnsatisfiableLength(
+                    format!("{} required characters do not fit in an exact length of {}", class_total, exact_length)));
+            }
+            return Ok((exact_length - class_total, Some(exact_length - class_total)));
+        }
+        match self.maximum_length {
+            Some(maximum_length) => {
+                if self.minimum_length + class_total > maximum_length {
+                    return Err(GenerationError::UnsatisfiableLength(
+                        format!("a minimum length of {} plus {} required characters exceeds the maximum length {}",
+                                self.minimum_length, class_total, maximum_length)));
                 }
+                Ok((self.minimum_length, Some(maximum_length - class_total)))
+            }
+            None => Ok((self.minimum_length, None)),
+        }
+    }
+    /// Distinct characters special characters are drawn from, after removing ambiguous ones if
+    /// requested
+    pub(crate) fn symbol_alphabet(&self) -> Result<Vec<char>, GenerationError> {
+        let mut symbols = parse_symbol_set(self.symbol_set.as_deref().unwrap_or(DEFAULT_SYMBOLS))?;
+        if self.exclude_ambiguous {
+            symbols.retain(|&ch| !is_ambiguous(ch));
+            if symbols.is_empty() {
+                return Err(GenerationError::InvalidSymbolSet(String::from("every symbol in the set is ambiguous")));
             }
         }
-        if self.use_words {
-            while password.len() < self.minimum_length {
-                let word = random_word::gen(random_word::Lang::En);
-                if !used_words.contains(&word) {
-                    for ch in word.chars() {
-                        password.push(ch);
+        Ok(symbols)
+    }
+    /// Loads the word list these settings refer to, checking it against `word_list_hash` if set
+    pub fn load_word_list(&self) -> Result<WordList, GenerationError> {
+        match (&self.word_list_path, &self.word_list_hash) {
+            (Some(path), Some(expected_hash)) => WordList::load_verified(path, expected_hash),
+            (Some(path), None) => WordList::load(path),
+            (None, _) => WordList::builtin(self.language),
+        }
+    }
+    /// Words of `word_list` the generator may pick. Besides leaving out words with ambiguous
+    /// characters, this drops words that the word style would give an ambiguous capital `I` or `O`.
+    pub(crate) fn usable_words<'a>(&self, word_list: &'a WordList) -> Vec<&'a str> {
+        let mut words = word_list.words(self.exclude_ambiguous);
+        if self.exclude_ambiguous && self.word_style != WordStyle::Lower {
+            let capitalized_ambiguous = |ch: char| is_ambiguous(uppercase_char(ch));
+            words.retain(|word| {
+                if self.word_style.uppercases_whole_words() {
+                    !word.chars().any(capitalized_ambiguous)
+                }
+                else{
+                    !word.chars().next().is_some_and(capitalized_ambiguous)
+                }
+            });
+        }
+        words
+    }
+    /// Applies the word style and leetspeak to the chosen words
+    fn style_words<R: RngCore + CryptoRng>(&self, rng: &mut R, words: &[&str]) -> Vec<String> {
+        let caps_idx = if words.is_empty() {0} else {rng.gen_range(0..words.len())};
+        let mut styled_words = Vec::new();
+        for (word_idx, word) in words.iter().enumerate() {
+            let all_caps = match self.word_style {
+                WordStyle::RandomCase => rng.gen_bool(0.5),
+                WordStyle::OneCaps => word_idx == caps_idx,
+                _ => false,
+            };
+            let capitalize_first = match self.word_style {
+                WordStyle::Title => true,
+                WordStyle::Camel => word_idx > 0,
+                _ => false,
+            };
+            let styled: String = word.chars().enumerate().map(|(ch_idx, ch)| {
+                if all_caps || (capitalize_first && ch_idx == 0) {
+                    uppercase_char(ch)
+                }
+                else if self.leetspeak {
+                    leet_char(ch, self.exclude_ambiguous)
+                }
+                else{
+                    ch
+                }
+            }).collect();
+            styled_words.push(styled);
+        }
+        styled_words
+    }
+    /// Estimated bits of entropy of passwords generated with these settings, after any growth
+    /// needed to meet `min_entropy`. See the `entropy` module for how it is computed.
+    pub fn entropy_bits(&self) -> Result<f64, GenerationError> {
+        entropy::settings_entropy(&self.grown_to_min_entropy()?)
+    }
+    /// Returns a copy of the settings with `minimum_length` raised just far enough for the entropy
+    /// estimate to reach `min_entropy`. Exact lengths can't grow, and maximum lengths cap the growth.
+    fn grown_to_min_entropy(&self) -> Result<GenerationData, GenerationError> {
+        let mut settings = self.clone();
+        let required = match self.min_entropy {
+            Some(required) => check_min_entropy(required)?,
+            None => return Ok(settings),
+        };
+        let achievable = entropy::settings_entropy(&settings)?;
+        if achievable >= required {
+            return Ok(settings);
+        }
+        // passphrases have a set number of words, so their entropy doesn't depend on the length
+        let fixed_length = settings.token_format.is_some() || settings.pin_length.is_some() || settings.pattern.is_some()
+            || settings.word_count.is_some() || settings.exact_length.is_some();
+        let class_total = class_total(&[self.min_numbers, self.min_special, self.min_ucase])?;
+        let longest = match self.maximum_length {
+            Some(maximum_length) => maximum_length.saturating_sub(class_total).min(MAX_PASSWORD_LENGTH),
+            None => MAX_PASSWORD_LENGTH,
+        };
+        if fixed_length || longest <= self.minimum_length {
+            return Err(GenerationError::EntropyTooLow { required, achievable });
+        }
+        let entropy_with_length = |minimum_length: usize| {
+            let mut grown = self.clone();
+            grown.minimum_length = minimum_length;
+            entropy::settings_entropy(&grown)
+        };
+        let achievable = entropy_with_length(longest)?;
+        if achievable < required {
+            return Err(GenerationError::EntropyTooLow { required, achievable });
+        }
+        // the estimate grows with the length, so bisect between a length that falls short and
+        // one that reaches the target
+        let (mut short, mut long) = (self.minimum_length, longest);
+        while long - short > 1 {
+            let middle = short + (long - short) / 2;
+            if entropy_with_length(middle)? >= required {
+                long = middle;
+            }
+            else{
+                short = middle;
+            }
+        }
+        settings.minimum_length = long;
+        Ok(settings)
+    }
+    /// Generates a password from the current settings using the operating system's entropy
+    /// source. Profile lookups are the caller's responsibility; this only reads the fields of `self`.
+    pub fn generate_password(&self) -> Result<String, GenerationError> {
+        self.generate_password_with_rng(&mut OsRng)
+    }
+    /// Same as `generate_password`, but draws all randomness from `rng`. Passing a seeded
+    /// generator such as `rand_chacha::ChaCha20Rng` gives reproducible output.
+    pub fn generate_password_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Result<String, GenerationError> {
+        let mut passwords = self.generate_batch_with_rng(rng, 1)?;
+        Ok(passwords.remove(0))
+    }
+    /// Generates `count` passwords that are all different from one another, using the operating
+    /// system's entropy source
+    pub fn generate_batch(&self, count: usize) -> Result<Vec<String>, GenerationError> {
+        self.generate_batch_with_rng(&mut OsRng, count)
+    }
+    /// Same as `generate_batch`, but draws all randomness from `rng`. The word list is loaded and
+    /// filtered once for the whole batch. Duplicates are redrawn, and if the settings allow so few
+    /// passwords that duplicates keep coming up, `GenerationError::NotEnoughDistinct` is returned.
+    /// Batches larger than `MAX_BATCH_SIZE` return `GenerationError::BatchTooLarge`.
+    pub fn generate_batch_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R, count: usize) -> Result<Vec<String>, GenerationError> {
+        if count > MAX_BATCH_SIZE {
+            return Err(GenerationError::BatchTooLarge(count));
+        }
+        let settings = match self.min_entropy {
+            Some(_) => self.grown_to_min_entropy()?,
+            None => self.clone(),
+        };
+        let word_list = settings.mode_word_list()?;
+        let words = match &word_list {
+            Some(word_list) => settings.mode_words(word_list)?,
+            None => Vec::new(),
+        };
+        let mut passwords = Vec::new();
+        let mut seen = HashSet::new();
+        let mut duplicates = 0;
+        while passwords.len() < count {
+            let password = settings.fill_password(rng, &words)?;
+            if seen.insert(password.clone()) {
+                passwords.push(password);
+            }
+            else{
+                duplicates += 1;
+                if duplicates > MAX_DUPLICATE_PASSWORDS {
+                    return Err(GenerationError::NotEnoughDistinct { requested: count, generated: passwords.len() });
+                }
+            }
+        }
+        Ok(passwords)
+    }
+    /// Loads the word list if the generation mode draws words from it
+    fn mode_word_list(&self) -> Result<Option<WordList>, GenerationError> {
+        let needs_words = if self.token_format.is_some() || self.pin_length.is_some() {
+            false
+        }
+        else if let Some(pattern) = &self.pattern {
+            Pattern::parse(pattern)?.uses_words()
+        }
+        else{
+            self.word_count.is_some() || (self.use_words && !self.pronounceable)
+        };
+        if needs_words {Ok(Some(self.load_word_list()?))} else {Ok(None)}
+    }
+    /// The words of `word_list` the generation mode draws from, which must not be empty
+    fn mode_words<'a>(&self, word_list: &'a WordList) -> Result<Vec<&'a str>, GenerationError> {
+        let words = match &self.pattern {
+            Some(pattern) => Pattern::parse(pattern)?.usable_words(word_list, self.exclude_ambiguous),
+            None => self.usable_words(word_list),
+        };
+        if words.is_empty() {
+            return Err(GenerationError::InvalidWordList(String::from("no usable words are left in the list")));
+        }
+        Ok(words)
+    }
+    /// Parses `pattern` and loads the words its word tokens draw from, if it has any
+    pub(crate) fn pattern_plan(&self, pattern: &str) -> Result<(Pattern, Option<WordList>), GenerationError> {
+        let pattern = Pattern::parse(pattern)?;
+        let word_list = if pattern.uses_words() {Some(self.load_word_list()?)} else {None};
+        if let Some(word_list) = &word_list {
+            if pattern.usable_words(word_list, self.exclude_ambiguous).is_empty() {
+                return Err(GenerationError::InvalidWordList(String::from("no usable words are left in the list")));
+            }
+        }
+        Ok((pattern, word_list))
+    }
+    /// The block list of PIN mode as a set
+    pub(crate) fn pin_blocklist_set(&self) -> Result<HashSet<String>, GenerationError> {
+        pin::parse_blocklist(self.pin_blocklist.as_deref().unwrap_or(""))
+    }
+    /// Number of random bytes a token uses, after checking the token settings
+    pub(crate) fn checked_token_bytes(&self, format: TokenFormat) -> Result<usize, GenerationError> {
+        if let Some(prefix) = &self.token_prefix {
+            if prefix.chars().any(|ch| ch.is_whitespace() || ch.is_control()) {
+                return Err(GenerationError::InvalidToken(format!("the prefix '{}' contains whitespace", prefix)));
+            }
+        }
+        match (format, self.token_bytes.unwrap_or(token::DEFAULT_TOKEN_BYTES)) {
+            (TokenFormat::Uuid, _) => Ok(16),
+            (_, 0) => Err(GenerationError::EmptyPassword),
+            (_, byte_count) if byte_count > token::MAX_TOKEN_BYTES => Err(token::too_many_bytes(byte_count)),
+            (_, byte_count) => Ok(byte_count),
+        }
+    }
+    /// Derives the password for `site`, `username` and `counter` from `master_secret`, so the
+    /// same inputs and settings always give the same password. See the `derive` module.
+    pub fn derive_password(&self, master_secret: &[u8]) -> Result<String, GenerationError> {
+        let site = self.site.as_deref()
+            .ok_or_else(|| GenerationError::Derivation(String::from("a site is required")))?;
+        let username = self.username.as_deref().unwrap_or("");
+        let mut rng = derive::derived_rng(self.derivation, master_secret, site, username, self.counter)?;
+        self.generate_password_with_rng(&mut rng)
+    }
+    /// Generates one password, drawing any words from `words`
+    fn fill_password<R: RngCore + CryptoRng>(&self, rng: &mut R, words: &[&str]) -> Result<String, GenerationError> {
+        if let Some(format) = self.token_format {
+            let byte_count = self.checked_token_bytes(format)?;
+            let prefix = self.token_prefix.as_deref().unwrap_or("");
+            return token::generate_token(rng, format, byte_count, prefix, self.token_checksum);
+        }
+        if let Some(pin_length) = self.pin_length {
+            self.check_length_limits()?;
+            let digits = pin::pin_digits(self.exclude_ambiguous);
+            return pin::generate_pin(rng, pin_length, &digits, &self.pin_rules, &self.pin_blocklist_set()?);
+        }
+        if let Some(pattern) = &self.pattern {
+            return Ok(Pattern::parse(pattern)?.generate(rng, &self.symbol_alphabet()?, words, self.exclude_ambiguous));
+        }
+        let num_numbers = class_count(rng, "numbers", self.min_numbers, self.max_numbers)?;
+        let num_special = class_count(rng, "special characters", self.min_special, self.max_special)?;
+        let num_ucase = class_count(rng, "uppercase characters", self.min_ucase, self.max_ucase)?;
+        let symbols = self.symbol_alphabet()?;
+        let class_chars = class_total(&[num_numbers, num_special, num_ucase])?;
+        let (base_lower, base_upper) = self.base_length_bounds(class_chars)?;
+        let base_is_empty = match self.word_count {
+            Some(word_count) => word_count == 0,
+            None => base_lower == 0,
+        };
+        if base_is_empty && class_chars == 0 {
+            return Err(GenerationError::EmptyPassword);
+        }
+        let uses_word_list = self.word_count.is_some() || (self.use_words && !self.pronounceable);
+        // lengths are counted in characters rather than bytes, since words in languages other than
+        // English may contain non-ASCII letters
+        let mut password = String::new();
+        let mut num_ucase = num_ucase;
+        if let Some(word_count) = self.word_count {
+            // uppercase letters from the word style count towards the uppercase requirement
+            num_ucase = num_ucase.saturating_sub(self.word_style.guaranteed_uppercase(word_count));
+            let chosen_words: Vec<&str> = (0..word_count).map(|_| generate_word(rng, words)).collect();
+            for (word_idx, word) in self.style_words(rng, &chosen_words).iter().enumerate() {
+                if word_idx > 0 {
+                    match self.separator {
+                        Separator::None => {}
+                        Separator::Space => password.push(' '),
+                        Separator::Hyphen => password.push('-'),
+                        Separator::Digit => password.push(generate_numeric_char(rng, self.exclude_ambiguous)),
+                        Separator::Symbol => password.push(generate_special_char(rng, &symbols)),
+                    }
+                }
+                password.push_str(word);
+            }
+        }
+        else if self.pronounceable {
+            let (consonants, vowels) = pronounceable_alphabets(self.exclude_ambiguous);
+            for idx in 0..base_lower {
+                let alphabet = if idx % 2 == 0 {&consonants} else {&vowels};
+                password.push(*alphabet.choose(rng).expect("alphabet is empty"));
+            }
+        }
+        else if self.use_words {
+            let chosen_words = match base_upper {
+                Some(base_upper) => generate_words_within(rng, words, base_lower, base_upper)?,
+                None => {
+                    // words are never repeated, so a short list may run out before the length is met
+                    if !distinct_words_reach(words, base_lower) {
+                        return Err(GenerationError::UnsatisfiableLength(
+                            format!("the word list doesn't have enough distinct words for {} characters", base_lower)));
                     }
-                    used_words.insert(word);
+                    let mut chosen_words = Vec::new();
+                    let mut used_words = HashSet::new();
+                    let mut length = 0;
+                    while length < base_lower {
+                        let word = generate_word(rng, words);
+                        if !used_words.contains(&word) {
+                            chosen_words.push(word);
+                            length += word.chars().count();
+                            used_words.insert(word);
+                        }
+                    }
+                    chosen_words
                 }
+            };
+            password = self.style_words(rng, &chosen_words).concat();
+        }
+        else{
+            while password.len() < base_lower {
+                password.push(generate_lcase_char(rng, self.exclude_ambiguous));
             }
         }
+        let mut required_chars = Vec::new();
+        for _ in 0..num_numbers {
+            required_chars.push(generate_numeric_char(rng, self.exclude_ambiguous));
+        }
+        for _ in 0..num_special {
+            required_chars.push(generate_special_char(rng, &symbols));
+        }
+        for _ in 0..num_ucase {
+            required_chars.push(generate_ucase_char(rng, self.exclude_ambiguous));
+        }
+        let mut password: Vec<char> = password.chars().collect();
+        if uses_word_list || self.pronounceable {
+            insert_at_random_positions(&mut password, required_chars, rng);
+        }
         else{
-            while password.len() < self.minimum_length {
-                password.push(generate_lcase_char());
+            // every character is independent in this mode, so shuffling the whole sequence is
+            // enough to hide which positions hold the required classes
+            password.extend(required_chars);
+            password.shuffle(rng);
+        }
+        Ok(password.into_iter().collect())
+    }
+}
+/// The words that fit a length budget of `lower` to `upper` characters, grouped by length, and
+/// which remaining needs can still be met with them
+struct WordBudget<'a> {
+    words_by_length: Vec<Vec<&'a str>>,
+    word_lengths: Vec<usize>,
+    lower: usize,
+    upper: usize,
+    /// `completable[n]`: a remaining need of `n` characters can still be met without exceeding
+    /// the budget, which is always exactly `upper - lower` more than the need
+    completable: Vec<bool>,
+}
+impl<'a> WordBudget<'a> {
+    fn new(words: &[&'a str], lower: usize, upper: usize) -> Result<WordBudget<'a>, GenerationError> {
+        // the budget itself may be far longer than any word
+        let longest = words.iter().map(|word| word.chars().count()).filter(|&length| length <= upper).max().unwrap_or(0);
+        let mut words_by_length: Vec<Vec<&'a str>> = vec![Vec::new(); longest + 1];
+        for &word in words {
+            let length = word.chars().count();
+            if length <= upper {
+                words_by_length[length].push(word);
+            }
+        }
+        let word_lengths: Vec<usize> = (1..=longest).filter(|&length| !words_by_length[length].is_empty()).collect();
+        let slack = upper - lower;
+        let mut completable = vec![true; lower + 1];
+        for need in 1..=lower {
+            completable[need] = word_lengths.iter().any(|&length| {
+                (need <= length && length <= need + slack) || (length < need && completable[need - length])
+            });
+        }
+        if !completable[lower] {
+            return Err(GenerationError::UnsatisfiableLength(
+                format!("no combination of dictionary words is between {} and {} characters long", lower, upper)));
+        }
+        Ok(WordBudget { words_by_length, word_lengths, lower, upper, completable })
+    }
+    /// Lengths of the words that may come next once `length` characters have been chosen
+    fn candidate_lengths(&self, length: usize) -> Vec<usize> {
+        let need = self.lower - length;
+        let budget = self.upper - length;
+        self.word_lengths.iter()
+            .copied()
+            .filter(|&length| length <= budget && (length >= need || self.completable[need - length]))
+            .collect()
+    }
+    fn candidate_count(&self, candidate_lengths: &[usize]) -> usize {
+        candidate_lengths.iter().map(|&length| self.words_by_length[length].len()).sum()
+    }
+}
+/// Picks random distinct words until their combined length lies between `lower` and `upper`.
+/// Each word is drawn uniformly from those that still leave a way to finish inside the bounds, so
+/// the result never overshoots and never gets stuck.
+fn generate_words_within<'a, R: RngCore + CryptoRng>(rng: &mut R, words: &[&'a str], lower: usize, upper: usize) -> Result<Vec<&'a str>, GenerationError> {
+    let budget = WordBudget::new(words, lower, upper)?;
+    let mut chosen_words = Vec::new();
+    let mut length = 0;
+    let mut used_words = HashSet::new();
+    while length < lower {
+        let candidate_lengths = budget.candidate_lengths(length);
+        let candidate_count = budget.candidate_count(&candidate_lengths);
+        // draw uniformly across all candidate words, redrawing already used ones
+        let mut attempts = 0;
+        let word = loop {
+            attempts += 1;
+            if attempts > MAX_WORD_ATTEMPTS {
+                return Err(GenerationError::UnsatisfiableLength(
+                    format!("ran out of distinct dictionary words between {} and {} characters long", lower, upper)));
+            }
+            let mut idx = rng.gen_range(0..candidate_count);
+            let mut length_idx = 0;
+            while idx >= budget.words_by_length[candidate_lengths[length_idx]].len() {
+                idx -= budget.words_by_length[candidate_lengths[length_idx]].len();
+                length_idx += 1;
+            }
+            let word = budget.words_by_length[candidate_lengths[length_idx]][idx];
+            if !used_words.contains(word) {
+                break word;
+            }
+        };
+        chosen_words.push(word);
+        length += word.chars().count();
+        used_words.insert(word);
+    }
+    Ok(chosen_words)
+}
+/// Bits of entropy and expected number of words of `generate_words_within` for the same bounds.
+/// Only the words that can actually be drawn at each step count, so tight bounds that leave few
+/// usable words give a low estimate. Redraws of words already used are ignored.
+pub(crate) fn words_within_entropy(words: &[&str], lower: usize, upper: usize) -> Result<(f64, f64), GenerationError> {
+    let budget = WordBudget::new(words, lower, upper)?;
+    // bits[n] and word_counts[n]: what is still to come once n characters have been chosen
+    let mut bits = vec![0.0; lower + 1];
+    let mut word_counts = vec![0.0; lower + 1];
+    for length in (0..lower).rev() {
+        let candidate_lengths = budget.candidate_lengths(length);
+        let candidate_count = budget.candidate_count(&candidate_lengths);
+        if candidate_count == 0 {
+            // no word choice ever leads here
+            continue;
+        }
+        bits[length] = (candidate_count as f64).log2();
+        word_counts[length] = 1.0;
+        for word_length in candidate_lengths {
+            let share = budget.words_by_length[word_length].len() as f64 / candidate_count as f64;
+            let next = (length + word_length).min(lower);
+            bits[length] += share * bits[next];
+            word_counts[length] += share * word_counts[next];
+        }
+    }
+    Ok((bits[0], word_counts[0]))
+}
+/// Whether the words of `words`, each used once, add up to at least `length` characters. Word
+/// lists are free of duplicates, so this stops as soon as enough words have been counted.
+fn distinct_words_reach(words: &[&str], length: usize) -> bool {
+    let mut total = 0;
+    length == 0 || words.iter().any(|word| {
+        total += word.chars().count();
+        total >= length
+    })
+}
+/// Longest password the minimum length, exact length, word count and class counts may ask for
+pub const MAX_PASSWORD_LENGTH: usize = 10_000;
+/// Highest entropy target `min_entropy` accepts, in bits
+pub const MAX_MIN_ENTROPY: f64 = 4096.0;
+/// Returns `bits` if it is a usable entropy target: finite, not negative and at most
+/// `MAX_MIN_ENTROPY`. Larger targets would need passwords longer than any system accepts.
+pub fn check_min_entropy(bits: f64) -> Result<f64, GenerationError> {
+    if bits.is_finite() && (0.0..=MAX_MIN_ENTROPY).contains(&bits) {
+        Ok(bits)
+    }
+    else{
+        Err(GenerationError::InvalidMinEntropy(bits))
+    }
+}
+/// Adds up class counts, returning `GenerationError::UnsatisfiableLength` instead of overflowing
+/// when they exceed `MAX_PASSWORD_LENGTH`
+pub(crate) fn class_total(counts: &[usize]) -> Result<usize, GenerationError> {
+    counts.iter().try_fold(0usize, |total, &count| total.checked_add(count))
+        .filter(|&total| total <= MAX_PASSWORD_LENGTH)
+        .ok_or_else(|| GenerationError::UnsatisfiableLength(
+            format!("more than {} required characters were requested", MAX_PASSWORD_LENGTH)))
+}
+/// Most passwords `generate_batch` makes at once
+pub const MAX_BATCH_SIZE: usize = 10_000;
+/// Duplicate passwords allowed before `generate_batch_with_rng` gives up on a batch
+const MAX_DUPLICATE_PASSWORDS: usize = 10_000;
+/// Redraws allowed before `generate_words_within` gives up on finding an unused word
+const MAX_WORD_ATTEMPTS: usize = 10_000;
+/// Picks how many characters of a class to include given its bounds
+fn class_count<R: RngCore + CryptoRng>(rng: &mut R, class: &'static str, minimum: usize, maximum: Option<usize>) -> Result<usize, GenerationError> {
+    match maximum {
+        None => Ok(minimum),
+        Some(maximum) if maximum < minimum => Err(GenerationError::InvalidCountRange { class, minimum, maximum }),
+        Some(maximum) => Ok(rng.gen_range(minimum..=maximum)),
+    }
+}
+/// Inserts each of `chars` at a uniformly random position of `password` (including the very start
+/// and end). Doing this one character at a time yields a uniformly random interleaving, so no
+/// position is more likely than another to hold an inserted character.
+fn insert_at_random_positions<R: RngCore + CryptoRng>(password: &mut Vec<char>, chars: Vec<char>, rng: &mut R) {
+    for ch in chars {
+        let idx = rng.gen_range(0..=password.len());
+        password.insert(idx, ch);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        insert_at_random_positions,
+        Language,
+        Separator,
+        WordStyle,
+        is_ambiguous,
+        DEFAULT_SYMBOLS,
+        PRONOUNCEABLE_CONSONANTS,
+        PRONOUNCEABLE_VOWELS,
+        GenerationData,
+        GenerationError,
+        MAX_MIN_ENTROPY,
+    };
+    use crate::token::TokenFormat;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn default_settings_meet_minimum_length() {
+        let settings = GenerationData::new(None, None, None, None, None, None);
+        let password = settings.generate_password().unwrap();
+        assert!(password.len() >= 8);
+    }
+    #[test]
+    fn character_classes_are_included() {
+        let settings = GenerationData::new(Some(12), Some(1), Some(1), Some(1), Some(false), None);
+        let password = settings.generate_password().unwrap();
+        assert_eq!(password.len(), 15);
+        assert!(password.chars().any(|ch| ch.is_ascii_digit()));
+        assert!(password.chars().any(|ch| ch.is_ascii_uppercase()));
+    }
+    #[test]
+    fn empty_settings_return_error() {
+        let settings = GenerationData::new(Some(0), Some(0), Some(0), Some(0), None, None);
+        assert_eq!(settings.generate_password(), Err(GenerationError::EmptyPassword));
+    }
+    #[test]
+    fn seeded_rng_gives_reproducible_passwords() {
+        let word_settings = GenerationData::new(Some(16), Some(1), Some(1), Some(1), Some(true), None);
+        let char_settings = GenerationData::new(Some(16), Some(1), Some(1), Some(1), Some(false), None);
+        for settings in [word_settings, char_settings] {
+            let first = settings.generate_password_with_rng(&mut ChaCha20Rng::seed_from_u64(42)).unwrap();
+            let second = settings.generate_password_with_rng(&mut ChaCha20Rng::seed_from_u64(42)).unwrap();
+            let other_seed = settings.generate_password_with_rng(&mut ChaCha20Rng::seed_from_u64(43)).unwrap();
+            assert_eq!(first, second);
+            assert_ne!(first, other_seed);
+        }
+    }
+    #[test]
+    fn minimum_counts_are_met() {
+        let settings = GenerationData::new(Some(10), Some(3), Some(0), Some(2), Some(false), None);
+        let password = settings.generate_password().unwrap();
+        assert_eq!(password.len(), 15);
+        assert_eq!(password.chars().filter(|ch| ch.is_ascii_digit()).count(), 3);
+        assert_eq!(password.chars().filter(|ch| ch.is_ascii_uppercase()).count(), 2);
+    }
+    #[test]
+    fn counts_stay_within_maximum() {
+        let mut settings = GenerationData::new(Some(10), Some(1), Some(0), Some(0), Some(false), None);
+        settings.max_numbers = Some(3);
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let mut seen_counts = [false; 4];
+        for _ in 0..200 {
+            let password = settings.generate_password_with_rng(&mut rng).unwrap();
+            let digit_count = password.chars().filter(|ch| ch.is_ascii_digit()).count();
+            assert!((1..=3).contains(&digit_count));
+            seen_counts[digit_count] = true;
+        }
+        assert_eq!(seen_counts, [false, true, true, true]);
+    }
+    #[test]
+    fn special_characters_are_symbols() {
+        let settings = GenerationData::new(Some(0), Some(0), Some(50), Some(0), Some(false), None);
+        let password = settings.generate_password().unwrap();
+        assert_eq!(password.len(), 50);
+        assert!(password.chars().all(|ch| DEFAULT_SYMBOLS.contains(ch)));
+    }
+    #[test]
+    fn custom_symbol_set_is_respected() {
+        let mut settings = GenerationData::new(Some(8), Some(0), Some(20), Some(0), Some(false), None);
+        settings.symbol_set = Some(String::from("!@#$%"));
+        let password = settings.generate_password().unwrap();
+        assert_eq!(password.chars().filter(|ch| "!@#$%".contains(*ch)).count(), 20);
+        assert!(password.chars().all(|ch| ch.is_ascii_lowercase() || "!@#$%".contains(ch)));
+    }
+    #[test]
+    fn invalid_symbol_sets_return_error() {
+        let mut settings = GenerationData::new(None, None, Some(1), None, None, None);
+        for symbol_set in ["", "!a#", "! #"] {
+            settings.symbol_set = Some(String::from(symbol_set));
+            assert!(matches!(settings.generate_password(), Err(GenerationError::InvalidSymbolSet(_))));
+        }
+    }
+    #[test]
+    fn ambiguous_characters_are_excluded() {
+        let mut char_settings = GenerationData::new(Some(200), Some(50), Some(50), Some(50), Some(false), None);
+        char_settings.exclude_ambiguous = true;
+        let mut word_settings = GenerationData::new(Some(200), Some(0), Some(0), Some(0), Some(true), None);
+        word_settings.exclude_ambiguous = true;
+        for settings in [char_settings, word_settings] {
+            let password = settings.generate_password().unwrap();
+            assert!(!password.chars().any(is_ambiguous));
+        }
+    }
+    #[test]
+    fn fully_ambiguous_symbol_set_returns_error() {
+        let mut settings = GenerationData::new(None, None, Some(1), None, None, None);
+        settings.symbol_set = Some(String::from("|"));
+        settings.exclude_ambiguous = true;
+        assert!(matches!(settings.generate_password(), Err(GenerationError::InvalidSymbolSet(_))));
+    }
+    #[test]
+    fn exact_length_is_respected() {
+        let mut rng = ChaCha20Rng::seed_from_u64(5);
+        for use_words in [false, true] {
+            for exact_length in [6, 9, 16, 20] {
+                let mut settings = GenerationData::new(Some(12), Some(1), Some(1), Some(1), Some(use_words), None);
+                settings.exact_length = Some(exact_length);
+                let password = settings.generate_password_with_rng(&mut rng).unwrap();
+                assert_eq!(password.len(), exact_length);
             }
         }
-        if self.include_numbers{
-            password.push(generate_numeric_char());
+    }
+    #[test]
+    fn maximum_length_is_respected() {
+        let mut rng = ChaCha20Rng::seed_from_u64(6);
+        let mut settings = GenerationData::new(Some(12), Some(1), Some(1), Some(1), Some(true), None);
+        settings.maximum_length = Some(16);
+        for _ in 0..50 {
+            let password = settings.generate_password_with_rng(&mut rng).unwrap();
+            assert!(password.len() >= 15 && password.len() <= 16);
         }
-        if self.include_special {
-            password.push(generate_special_char());
+    }
+    #[test]
+    fn unsatisfiable_lengths_return_error() {
+        let mut too_many_classes = GenerationData::new(None, Some(3), Some(3), Some(0), None, None);
+        too_many_classes.exact_length = Some(5);
+        let mut minimum_above_maximum = GenerationData::new(Some(16), Some(1), Some(0), Some(0), None, None);
+        minimum_above_maximum.maximum_length = Some(16);
+        let mut exact_above_maximum = GenerationData::new(None, None, None, None, None, None);
+        exact_above_maximum.exact_length = Some(20);
+        exact_above_maximum.maximum_length = Some(16);
+        for settings in [too_many_classes, minimum_above_maximum, exact_above_maximum] {
+            assert!(matches!(settings.generate_password(), Err(GenerationError::UnsatisfiableLength(_))));
+        }
+    }
+    #[test]
+    fn huge_lengths_return_error_instead_of_overflowing() {
+        let mut unbounded = GenerationData::new(None, Some(1), None, None, None, None);
+        unbounded.maximum_length = Some(usize::MAX);
+        assert!(unbounded.generate_password().unwrap().chars().count() >= 9);
+        let mut huge_minimum = GenerationData::new(Some(usize::MAX), Some(1), None, None, None, None);
+        huge_minimum.maximum_length = Some(5);
+        let mut huge_class = GenerationData::new(None, Some(usize::MAX), Some(1), None, None, None);
+        huge_class.exact_length = Some(5);
+        let huge_letters = GenerationData::new(Some(2_000_000_000), None, None, None, Some(false), None);
+        let mut huge_passphrase = GenerationData::new(None, None, None, None, None, None);
+        huge_passphrase.word_count = Some(usize::MAX);
+        let mut huge_pin = GenerationData::new(None, None, None, None, None, None);
+        huge_pin.pin_length = Some(usize::MAX);
+        for settings in [huge_minimum, huge_class, huge_letters, huge_passphrase, huge_pin] {
+            assert!(matches!(settings.generate_password(), Err(GenerationError::UnsatisfiableLength(_))));
+            assert!(matches!(settings.entropy_bits(), Err(GenerationError::UnsatisfiableLength(_))));
         }
-        if self.include_ucase {
-            password.push(generate_ucase_char());
+    }
+    #[test]
+    fn password_grows_to_meet_min_entropy() {
+        let mut settings = GenerationData::new(Some(8), Some(1), Some(0), Some(0), Some(false), None);
+        settings.min_entropy = Some(80.0);
+        assert!(settings.entropy_bits().unwrap() >= 80.0);
+        let password = settings.generate_password().unwrap();
+        // 8 letters carry under 40 bits, so the letters part has to grow to 16
+        assert_eq!(password.len(), 17);
+    }
+    #[test]
+    fn unreachable_min_entropy_returns_error() {
+        let mut settings = GenerationData::new(Some(8), Some(0), Some(0), Some(0), Some(false), None);
+        settings.min_entropy = Some(80.0);
+        settings.maximum_length = Some(12);
+        assert!(matches!(settings.generate_password(), Err(GenerationError::EntropyTooLow { .. })));
+    }
+    #[test]
+    fn unreachable_passphrase_entropy_returns_error() {
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), None, None);
+        settings.word_count = Some(3);
+        settings.min_entropy = Some(100.0);
+        assert!(matches!(settings.generate_password(), Err(GenerationError::EntropyTooLow { .. })));
+        assert!(matches!(settings.entropy_bits(), Err(GenerationError::EntropyTooLow { .. })));
+    }
+    #[test]
+    fn invalid_min_entropy_returns_error() {
+        let mut settings = GenerationData::new(Some(8), Some(0), Some(0), Some(0), Some(false), None);
+        for bits in [f64::INFINITY, f64::NAN, -1.0, 1e9] {
+            settings.min_entropy = Some(bits);
+            assert!(matches!(settings.generate_password(), Err(GenerationError::InvalidMinEntropy(_))));
+            assert!(matches!(settings.entropy_bits(), Err(GenerationError::InvalidMinEntropy(_))));
+        }
+        // the largest target is reached without growing one character at a time
+        settings.min_entropy = Some(MAX_MIN_ENTROPY);
+        let password = settings.generate_password().unwrap();
+        assert_eq!(password.len(), (MAX_MIN_ENTROPY / 26f64.log2()).ceil() as usize);
+    }
+    #[test]
+    fn passphrase_has_requested_word_count() {
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), None, None);
+        settings.word_count = Some(6);
+        settings.separator = Separator::Space;
+        let passphrase = settings.generate_password().unwrap();
+        assert_eq!(passphrase.split(' ').count(), 6);
+        settings.separator = Separator::Digit;
+        let passphrase = settings.generate_password().unwrap();
+        assert_eq!(passphrase.split(|ch: char| ch.is_ascii_digit()).count(), 6);
+    }
+    #[test]
+    fn passphrase_uses_word_list_file() {
+        let path = std::env::temp_dir().join("password_generator_test_eff_list.txt");
+        std::fs::write(&path, "11111\talpha\n11112\tbravo\n11113\tcharlie\n11114\tdelta\n").unwrap();
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), None, None);
+        settings.word_count = Some(5);
+        settings.word_list_path = Some(path.to_string_lossy().into_owned());
+        let passphrase = settings.generate_password().unwrap();
+        let entropy = settings.entropy_bits().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(passphrase.split('-').all(|word| ["alpha", "bravo", "charlie", "delta"].contains(&word)));
+        // five words from a list of four, two bits each
+        assert!((entropy - 10.0).abs() < 1e-9);
+    }
+    #[test]
+    fn short_word_list_returns_error_instead_of_running_out() {
+        let path = std::env::temp_dir().join("password_generator_test_tiny_list.txt");
+        std::fs::write(&path, "ab\ncd\n").unwrap();
+        let mut settings = GenerationData::new(Some(100), Some(0), Some(0), Some(0), Some(true), None);
+        settings.word_list_path = Some(path.to_string_lossy().into_owned());
+        let too_long = settings.generate_password();
+        settings.minimum_length = 4;
+        let password = settings.generate_password().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(too_long, Err(GenerationError::UnsatisfiableLength(_))));
+        assert!(password == "abcd" || password == "cdab");
+    }
+    #[test]
+    fn non_ascii_words_are_measured_in_characters() {
+        let path = std::env::temp_dir().join("password_generator_test_umlaut_list.txt");
+        std::fs::write(&path, "über\ngröße\nstraße\nmädchen\n").unwrap();
+        let mut settings = GenerationData::new(Some(10), Some(0), Some(0), Some(0), Some(true), None);
+        settings.word_list_path = Some(path.to_string_lossy().into_owned());
+        settings.exact_length = Some(11);
+        let mut rng = ChaCha20Rng::seed_from_u64(8);
+        for _ in 0..20 {
+            let password = settings.generate_password_with_rng(&mut rng).unwrap();
+            assert_eq!(password.chars().count(), 11);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[cfg(not(feature = "zh"))]
+    #[test]
+    fn language_without_feature_returns_error() {
+        let mut settings = GenerationData::new(None, None, None, None, Some(true), None);
+        settings.language = Language::Chinese;
+        assert_eq!(settings.generate_password(), Err(GenerationError::UnsupportedLanguage(Language::Chinese)));
+    }
+    #[cfg(feature = "de")]
+    #[test]
+    fn german_passphrase_uses_german_words() {
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), None, None);
+        settings.word_count = Some(4);
+        settings.language = Language::German;
+        let german = crate::word_list::WordList::builtin(Language::German).unwrap();
+        let german_words = german.words(false);
+        let passphrase = settings.generate_password().unwrap();
+        assert!(passphrase.split('-').all(|word| german_words.contains(&word)));
+    }
+    #[test]
+    fn word_styles_capitalize_words() {
+        let path = std::env::temp_dir().join("password_generator_test_style_list.txt");
+        std::fs::write(&path, "kayak\nmango\nzebra\n").unwrap();
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), None, None);
+        settings.word_count = Some(4);
+        settings.word_list_path = Some(path.to_string_lossy().into_owned());
+        let mut rng = ChaCha20Rng::seed_from_u64(9);
+        settings.word_style = WordStyle::Title;
+        let passphrase = settings.generate_password_with_rng(&mut rng).unwrap();
+        assert!(passphrase.split('-').all(|word| word.chars().next().unwrap().is_ascii_uppercase()));
+        settings.word_style = WordStyle::Camel;
+        settings.separator = Separator::None;
+        let passphrase = settings.generate_password_with_rng(&mut rng).unwrap();
+        assert!(passphrase.starts_with(|ch: char| ch.is_ascii_lowercase()));
+        assert_eq!(passphrase.chars().filter(|ch| ch.is_ascii_uppercase()).count(), 3);
+        settings.word_style = WordStyle::OneCaps;
+        settings.separator = Separator::Space;
+        let passphrase = settings.generate_password_with_rng(&mut rng).unwrap();
+        let caps_words = passphrase.split(' ').filter(|word| word.chars().all(|ch| ch.is_ascii_uppercase())).count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(caps_words, 1);
+    }
+    #[test]
+    fn word_style_capitals_count_towards_uppercase_minimum() {
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(2), None, None);
+        settings.word_count = Some(3);
+        settings.separator = Separator::Space;
+        settings.word_style = WordStyle::Title;
+        let passphrase = settings.generate_password().unwrap();
+        // three title case words already carry more than the two required capitals
+        assert_eq!(passphrase.split(' ').count(), 3);
+        assert!(passphrase.split(' ').all(|word| word.chars().skip(1).all(|ch| ch.is_ascii_lowercase())));
+    }
+    #[test]
+    fn leetspeak_keeps_length_and_respects_ambiguity() {
+        let path = std::env::temp_dir().join("password_generator_test_leet_list.txt");
+        std::fs::write(&path, "toast\n").unwrap();
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), None, None);
+        settings.word_count = Some(2);
+        settings.word_list_path = Some(path.to_string_lossy().into_owned());
+        settings.leetspeak = true;
+        assert_eq!(settings.generate_password().unwrap(), "70457-70457");
+        settings.exclude_ambiguous = true;
+        settings.word_style = WordStyle::Title;
+        let passphrase = settings.generate_password().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(passphrase, "To457-To457");
+    }
+    #[test]
+    fn random_case_adds_a_bit_per_word() {
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(0), None, None);
+        settings.word_count = Some(5);
+        let plain = settings.entropy_bits().unwrap();
+        settings.word_style = WordStyle::RandomCase;
+        assert!((settings.entropy_bits().unwrap() - plain - 5.0).abs() < 1e-9);
+        settings.word_style = WordStyle::Title;
+        assert!((settings.entropy_bits().unwrap() - plain).abs() < 1e-9);
+    }
+    #[test]
+    fn pronounceable_mode_alternates_consonants_and_vowels() {
+        let mut settings = GenerationData::new(Some(11), Some(2), Some(0), Some(1), Some(true), None);
+        settings.pronounceable = true;
+        settings.exact_length = Some(14);
+        let password = settings.generate_password_with_rng(&mut ChaCha20Rng::seed_from_u64(10)).unwrap();
+        assert_eq!(password.len(), 14);
+        let letters: Vec<char> = password.chars().filter(|ch| ch.is_ascii_lowercase()).collect();
+        assert_eq!(letters.len(), 11);
+        for (idx, ch) in letters.iter().enumerate() {
+            let alphabet = if idx % 2 == 0 {PRONOUNCEABLE_CONSONANTS} else {PRONOUNCEABLE_VOWELS};
+            assert!(alphabet.contains(*ch));
+        }
+    }
+    #[test]
+    fn pattern_replaces_length_and_class_options() {
+        let mut settings = GenerationData::new(Some(30), Some(3), Some(3), Some(3), Some(true), None);
+        settings.pattern = Some(String::from("Cvccvc-99-Cvccvc"));
+        settings.symbol_set = Some(String::from("!"));
+        let password = settings.generate_password().unwrap();
+        assert_eq!(password.len(), 16);
+        assert_eq!(password.chars().filter(|ch| ch.is_ascii_digit()).count(), 2);
+        assert!(!password.contains('!'));
+        settings.min_entropy = Some(200.0);
+        assert!(matches!(settings.generate_password(), Err(GenerationError::EntropyTooLow { .. })));
+    }
+    #[test]
+    fn pin_mode_generates_digits_only() {
+        let mut settings = GenerationData::new(Some(20), Some(2), Some(2), Some(2), Some(true), None);
+        settings.pin_length = Some(6);
+        settings.exclude_ambiguous = true;
+        let pin = settings.generate_password().unwrap();
+        assert_eq!(pin.len(), 6);
+        assert!(pin.chars().all(|ch| ('2'..='9').contains(&ch)));
+        settings.pin_length = Some(1);
+        settings.pin_blocklist = Some(String::from("2,3,4,5,6,7,8,9"));
+        assert!(matches!(settings.generate_password(), Err(GenerationError::InvalidPin(_))));
+        assert!(matches!(settings.entropy_bits(), Err(GenerationError::InvalidPin(_))));
+    }
+    #[test]
+    fn token_mode_ignores_password_options() {
+        let mut settings = GenerationData::new(Some(20), Some(2), Some(2), Some(2), Some(true), None);
+        settings.token_format = Some(TokenFormat::Hex);
+        settings.token_bytes = Some(16);
+        settings.token_prefix = Some(String::from("sk_test_"));
+        let token = settings.generate_password().unwrap();
+        assert_eq!(token.len(), "sk_test_".len() + 32);
+        assert!((settings.entropy_bits().unwrap() - 128.0).abs() < 1e-9);
+        settings.token_prefix = Some(String::from("sk test"));
+        assert!(matches!(settings.generate_password(), Err(GenerationError::InvalidToken(_))));
+        settings.token_prefix = None;
+        settings.token_bytes = Some(usize::MAX);
+        assert!(matches!(settings.generate_password(), Err(GenerationError::InvalidToken(_))));
+        assert!(matches!(settings.entropy_bits(), Err(GenerationError::InvalidToken(_))));
+    }
+    #[test]
+    fn batch_passwords_are_distinct() {
+        let settings = GenerationData::new(Some(10), Some(1), Some(1), Some(1), Some(true), None);
+        let mut rng = ChaCha20Rng::seed_from_u64(17);
+        let passwords = settings.generate_batch_with_rng(&mut rng, 50).unwrap();
+        assert_eq!(passwords.len(), 50);
+        let distinct: std::collections::HashSet<&String> = passwords.iter().collect();
+        assert_eq!(distinct.len(), 50);
+        assert!(passwords.iter().all(|password| password.chars().count() >= 10));
+        assert_eq!(settings.generate_batch_with_rng(&mut rng, 0), Ok(Vec::new()));
+        assert_eq!(settings.generate_batch_with_rng(&mut rng, usize::MAX), Err(GenerationError::BatchTooLarge(usize::MAX)));
+    }
+    #[test]
+    fn batch_larger_than_the_password_space_returns_error() {
+        let mut settings = GenerationData::new(None, None, None, None, None, None);
+        settings.pin_length = Some(1);
+        settings.pin_rules = crate::pin::PinRules::none();
+        let mut rng = ChaCha20Rng::seed_from_u64(18);
+        let mut pins = settings.generate_batch_with_rng(&mut rng, 10).unwrap();
+        pins.sort();
+        assert_eq!(pins.concat(), "0123456789");
+        assert_eq!(settings.generate_batch_with_rng(&mut rng, 11),
+                   Err(GenerationError::NotEnoughDistinct { requested: 11, generated: 10 }));
+    }
+    #[test]
+    fn maximum_below_minimum_returns_error() {
+        let mut settings = GenerationData::new(None, Some(0), Some(0), Some(3), None, None);
+        settings.max_ucase = Some(2);
+        assert_eq!(settings.generate_password(),
+                   Err(GenerationError::InvalidCountRange { class: "uppercase characters", minimum: 3, maximum: 2 }));
+    }
+    /// Pearson's chi-squared statistic of `counts` against a uniform distribution
+    fn chi_squared(counts: &[usize]) -> f64 {
+        let total: usize = counts.iter().sum();
+        let expected = total as f64 / counts.len() as f64;
+        counts.iter().map(|&count| (count as f64 - expected).powi(2) / expected).sum()
+    }
+    // 24.32 is the chi-squared critical value for 7 degrees of freedom at p = 0.001
+    const CRITICAL_VALUE_7_DOF: f64 = 24.32;
+    #[test]
+    fn required_classes_are_uniformly_placed_in_character_mode() {
+        let settings = GenerationData::new(Some(6), Some(1), Some(0), Some(1), Some(false), None);
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let mut digit_positions = [0usize; 8];
+        let mut ucase_positions = [0usize; 8];
+        for _ in 0..8000 {
+            let password: Vec<char> = settings.generate_password_with_rng(&mut rng).unwrap().chars().collect();
+            assert_eq!(password.len(), 8);
+            digit_positions[password.iter().position(|ch| ch.is_ascii_digit()).unwrap()] += 1;
+            ucase_positions[password.iter().position(|ch| ch.is_ascii_uppercase()).unwrap()] += 1;
+        }
+        assert!(chi_squared(&digit_positions) < CRITICAL_VALUE_7_DOF);
+        assert!(chi_squared(&ucase_positions) < CRITICAL_VALUE_7_DOF);
+    }
+    #[test]
+    fn inserted_characters_are_uniformly_placed() {
+        let mut rng = ChaCha20Rng::seed_from_u64(11);
+        let mut positions = [0usize; 8];
+        for _ in 0..8000 {
+            let mut password: Vec<char> = "abcdef".chars().collect();
+            insert_at_random_positions(&mut password, vec!['1', '2'], &mut rng);
+            assert_eq!(password.len(), 8);
+            positions[password.iter().position(|&ch| ch == '2').unwrap()] += 1;
         }
-        password
+        assert!(chi_squared(&positions) < CRITICAL_VALUE_7_DOF);
     }
 }
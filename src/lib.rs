@@ -0,0 +1,17 @@
+//! Library half of the password generator. Generation itself lives in `generation_logic` and is
+//! free of side effects: it never prints, never touches the profile database and never exits the
+//! process, so it can be embedded in other programs. Profile storage is available separately
+//! through the `database` module, whose schema is kept up to date by `migrations`.
+pub mod database;
+pub mod derive;
+pub mod entropy;
+pub mod error;
+pub mod generation_logic;
+pub mod migrations;
+pub mod pattern;
+pub mod pin;
+pub mod token;
+pub mod word_list;
+
+pub use error::Error;
+pub use generation_logic::{GenerationData, GenerationError};
@@ -1,36 +1,36 @@
-mod generation_logic;
 mod cli;
-mod database;
-use std::{env, process::exit};
+mod output;
+use cli::{Cli, Command};
+use password_generator::{database, Error};
+use std::path::Path;
+use std::process::exit;
+
+const LEGACY_DATABASE_PATH: &str = "profiles_database.db";
 fn main() {
-    let mut args: Vec<String> = env::args().collect();
-    let conn = database::create_connection();
-    println!("Command line tool for password generation with additional customization and features. Use --help option for more information.");
-    match  database::initialize_db(&conn) {
-        Ok(_) => {
-            println!("database check successful" );
-        }
-        Err(x) => {
-            println!("something went wrong when initializing the database:");
-            println!("{}", x)
-        }
+    // invalid arguments make clap exit with code 2, the same code as `Error::Parse`
+    let cli = Cli::try_parse_args(std::env::args_os()).unwrap_or_else(|x| x.exit());
+    if let Err(x) = run(cli) {
+        eprintln!("{}", x);
+        exit(x.exit_code());
     }
-    if args.len() >= 2{ // the first arg is always the executable path
-        args.remove(0);
-        let parsed_arguments = cli::parse_args(args);
-        for arg in &parsed_arguments{
-            println!("{:?}", arg);
-        }
-        let mut generation_features = cli::process_and_execute_args(Some(parsed_arguments));
-        let password = generation_features.generate_password();
-        println!("printing the newly generated password: ");
-        println!("{}", password);
+}
+/// Runs the tool, leaving the mapping of errors to exit codes to `main`. Only the results go to
+/// stdout; status messages go to stderr and are left out entirely with `--quiet`.
+fn run(cli: Cli) -> Result<(), Error> {
+    let path = cli::database_path(cli.db)?;
+    output::status(cli.quiet, "Command line tool for password generation with additional customization and features. Use --help option for more information.");
+    // earlier versions kept the database in the working directory
+    if !path.exists() && Path::new(LEGACY_DATABASE_PATH).exists() {
+        output::status(cli.quiet, &format!(
+            "Found '{}' in the current directory; profiles are now kept in '{}'. Move the file there or pass --db {} to keep using it.",
+            LEGACY_DATABASE_PATH, path.display(), LEGACY_DATABASE_PATH));
     }
-    else{
-        println!("No options provided. Creating a password using default settings...");
-        let mut generation_features = cli::construct_features(None);
-        let password = generation_features.generate_password();
-        println!("printing the newly generated password: ");
-        println!("{}", password);
+    let conn = database::create_connection(&path)?;
+    let command = cli.command.unwrap_or(Command::Generate(cli.generate));
+    // `db migrate` reports pending migrations itself, and must leave them alone for `--dry-run`
+    if !matches!(command, Command::Db(_)) {
+        cli::migrate(&conn, cli.quiet)?;
+        output::status(cli.quiet, "database check successful");
     }
+    cli::run_command(&conn, command, cli.quiet)
 }
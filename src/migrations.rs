@@ -0,0 +1,382 @@
+//! Schema migrations for the profile database. The schema version is kept in `PRAGMA
+//! user_version`; each step in `MIGRATIONS` moves it up by one and runs in its own transaction, so
+//! an interrupted migration leaves the database at the last completed version.
+//!
+//! Steps are append-only: once released, a step's SQL never changes, and every schema change gets
+//! a new step at the end. The first step creates the table as the first release did.
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use crate::error::Error;
+
+/// One step from `version - 1` to `version`
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    sql: &'static str,
+}
+/// Every migration, in the order they are applied
+pub const MIGRATIONS: [Migration; 16] = [
+    Migration {
+        version: 1,
+        description: "create the password_settings table",
+        sql: "CREATE TABLE IF NOT EXISTS password_settings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            profile_name TEXT UNIQUE NOT NULL,
+            minimum_length INTEGER DEFAULT 8,
+            include_numbers BOOLEAN DEFAULT false,
+            include_special BOOLEAN DEFAULT false,
+            include_ucase BOOLEAN DEFAULT false,
+            use_words BOOLEAN DEFAULT true,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+    },
+    Migration {
+        version: 2,
+        description: "add minimum and maximum counts per character class",
+        sql: "ALTER TABLE password_settings ADD COLUMN min_numbers INTEGER DEFAULT 0;
+        ALTER TABLE password_settings ADD COLUMN max_numbers INTEGER;
+        ALTER TABLE password_settings ADD COLUMN min_special INTEGER DEFAULT 0;
+        ALTER TABLE password_settings ADD COLUMN max_special INTEGER;
+        ALTER TABLE password_settings ADD COLUMN min_ucase INTEGER DEFAULT 0;
+        ALTER TABLE password_settings ADD COLUMN max_ucase INTEGER;",
+    },
+    Migration {
+        version: 3,
+        description: "turn the include_* flags into a minimum count of one",
+        sql: "UPDATE password_settings SET min_numbers = 1 WHERE include_numbers;
+        UPDATE password_settings SET min_special = 1 WHERE include_special;
+        UPDATE password_settings SET min_ucase = 1 WHERE include_ucase;",
+    },
+    Migration {
+        version: 4,
+        description: "add the special character set",
+        sql: "ALTER TABLE password_settings ADD COLUMN symbol_set TEXT;",
+    },
+    Migration {
+        version: 5,
+        description: "add the exclude_ambiguous flag",
+        sql: "ALTER TABLE password_settings ADD COLUMN exclude_ambiguous BOOLEAN DEFAULT false;",
+    },
+    Migration {
+        version: 6,
+        description: "add maximum and exact lengths",
+        sql: "ALTER TABLE password_settings ADD COLUMN maximum_length INTEGER;
+        ALTER TABLE password_settings ADD COLUMN exact_length INTEGER;",
+    },
+    Migration {
+        version: 7,
+        description: "add the minimum entropy target",
+        sql: "ALTER TABLE password_settings ADD COLUMN min_entropy REAL;",
+    },
+    Migration {
+        version: 8,
+        description: "add passphrase word counts, separators and word list files",
+        sql: "ALTER TABLE password_settings ADD COLUMN word_count INTEGER;
+        ALTER TABLE password_settings ADD COLUMN separator TEXT DEFAULT 'hyphen';
+        ALTER TABLE password_settings ADD COLUMN word_list_path TEXT;",
+    },
+    Migration {
+        version: 9,
+        description: "add word list hashes",
+        sql: "ALTER TABLE password_settings ADD COLUMN word_list_hash TEXT;",
+    },
+    Migration {
+        version: 10,
+        description: "add the word list language",
+        sql: "ALTER TABLE password_settings ADD COLUMN language TEXT DEFAULT 'en';",
+    },
+    Migration {
+        version: 11,
+        description: "add word styles and leetspeak",
+        sql: "ALTER TABLE password_settings ADD COLUMN word_style TEXT DEFAULT 'lower';
+        ALTER TABLE password_settings ADD COLUMN leetspeak BOOLEAN DEFAULT false;",
+    },
+    Migration {
+        version: 12,
+        description: "add pronounceable mode",
+        sql: "ALTER TABLE password_settings ADD COLUMN pronounceable BOOLEAN DEFAULT false;",
+    },
+    Migration {
+        version: 13,
+        description: "add patterns",
+        sql: "ALTER TABLE password_settings ADD COLUMN pattern TEXT;",
+    },
+    Migration {
+        version: 14,
+        description: "add PIN mode",
+        sql: "ALTER TABLE password_settings ADD COLUMN pin_length INTEGER;
+        ALTER TABLE password_settings ADD COLUMN pin_rules TEXT DEFAULT 'repeated,sequence,pattern,date,keypad';
+        ALTER TABLE password_settings ADD COLUMN pin_blocklist TEXT;",
+    },
+    Migration {
+        version: 15,
+        description: "add token formats",
+        sql: "ALTER TABLE password_settings ADD COLUMN token_format TEXT;
+        ALTER TABLE password_settings ADD COLUMN token_bytes INTEGER;
+        ALTER TABLE password_settings ADD COLUMN token_prefix TEXT;
+        ALTER TABLE password_settings ADD COLUMN token_checksum BOOLEAN DEFAULT false;",
+    },
+    Migration {
+        version: 16,
+        description: "add site-specific password derivation",
+        sql: "ALTER TABLE password_settings ADD COLUMN site TEXT;
+        ALTER TABLE password_settings ADD COLUMN username TEXT;
+        ALTER TABLE password_settings ADD COLUMN counter INTEGER DEFAULT 1;
+        ALTER TABLE password_settings ADD COLUMN derivation TEXT DEFAULT 'argon2id-chacha20-v1';",
+    },
+];
+/// The schema version this build creates and expects
+pub const LATEST_VERSION: u32 = MIGRATIONS.len() as u32;
+
+impl Migration {
+    /// The columns this step adds
+    fn added_columns(&self) -> impl Iterator<Item = &'static str> {
+        self.sql.split("ADD COLUMN ").skip(1).filter_map(|rest| rest.split_whitespace().next())
+    }
+}
+fn table_columns(conn: &Connection) -> rusqlite::Result<HashSet<String>> {
+    let mut statement = conn.prepare("SELECT name FROM pragma_table_info('password_settings');")?;
+    let columns = statement.query_map([], |row| row.get::<_, String>(0))?;
+    columns.collect()
+}
+/// Releases before migrations existed left no schema version, and created the table with the
+/// columns of their time. Such a table is at the last step whose columns it has; a step that adds
+/// no columns counts as applied along with the step before it.
+fn unversioned_schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    let existing = table_columns(conn)?;
+    if existing.is_empty() {
+        return Ok(0);
+    }
+    let mut version = 1;
+    for step in &MIGRATIONS[1..] {
+        if !step.added_columns().all(|column| existing.contains(column)) {
+            break;
+        }
+        version = step.version;
+    }
+    Ok(version)
+}
+/// The version recorded in `PRAGMA user_version`. A database that predates migrations is given
+/// the version its table matches, and a new one is at version 0.
+pub fn schema_version(conn: &Connection) -> Result<u32, Error> {
+    let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version == 0 {
+        return Ok(unversioned_schema_version(conn)?);
+    }
+    Ok(version)
+}
+/// The migrations that have yet to be applied, in order. A database written by a newer build is
+/// an error rather than something to downgrade.
+pub fn pending(conn: &Connection) -> Result<&'static [Migration], Error> {
+    let version = schema_version(conn)?;
+    if version > LATEST_VERSION {
+        return Err(Error::Migration(format!(
+            "the profile database has schema version {}, but this build only knows up to version {}", version, LATEST_VERSION)));
+    }
+    Ok(&MIGRATIONS[version as usize..])
+}
+/// Applies the pending migrations and returns them. Each step commits together with its new
+/// schema version.
+pub fn migrate(conn: &Connection) -> Result<&'static [Migration], Error> {
+    let steps = pending(conn)?;
+    // record the version found for a database that predates migrations
+    conn.pragma_update(None, "user_version", schema_version(conn)?)?;
+    for step in steps {
+        let transaction = conn.unchecked_transaction()?;
+        transaction.execute_batch(step.sql)?;
+        transaction.pragma_update(None, "user_version", step.version)?;
+        transaction.commit()?;
+    }
+    Ok(steps)
+}
+/// Backs up the database file next to itself before migrating, as `<file>.v<version>.bak`, adding
+/// a number if that name is taken. The copy is written by SQLite (`VACUUM INTO`), so it is
+/// consistent even while the database is open, and like the database it is readable by the owner
+/// only. Nothing is backed up when no migration is pending, when the database is new and holds no
+/// tables, or when it isn't backed by a file. Returns the backup's path.
+pub fn backup(conn: &Connection) -> Result<Option<PathBuf>, Error> {
+    let path = match conn.path() {
+        Some(path) if !path.is_empty() => Path::new(path),
+        _ => return Ok(None),
+    };
+    let tables: u32 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table';", [], |row| row.get(0))?;
+    if pending(conn)?.is_empty() || tables == 0 {
+        return Ok(None);
+    }
+    let version = schema_version(conn)?;
+    let backup_error = |backup: &Path, x: &dyn std::fmt::Display| {
+        Error::Migration(format!("could not back up '{}' to '{}': {}", path.display(), backup.display(), x))
+    };
+    let mut backup = PathBuf::from(format!("{}.v{}.bak", path.display(), version));
+    let mut number = 1;
+    loop {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(&backup) {
+            Ok(_) => break,
+            Err(x) if x.kind() == io::ErrorKind::AlreadyExists => {
+                backup = PathBuf::from(format!("{}.v{}.{}.bak", path.display(), version, number));
+                number += 1;
+            }
+            Err(x) => return Err(backup_error(&backup, &x)),
+        }
+    }
+    let target = backup.to_str().ok_or_else(|| backup_error(&backup, &"the path isn't valid UTF-8"))?;
+    if let Err(x) = conn.execute("VACUUM INTO ?1;", [target]) {
+        fs::remove_file(&backup).ok();
+        return Err(backup_error(&backup, &x));
+    }
+    Ok(Some(backup))
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        backup,
+        migrate,
+        pending,
+        schema_version,
+        table_columns,
+        LATEST_VERSION,
+        MIGRATIONS,
+    };
+    use crate::database::{insert_user_profile, retrieve_profile_settings, SettingColumn};
+    use crate::error::Error;
+    use crate::generation_logic::GenerationData;
+    use rusqlite::Connection;
+    use std::fs;
+
+    /// The table as created by the first release
+    fn create_first_release_table(conn: &Connection) {
+        conn.execute(
+            "CREATE TABLE password_settings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                profile_name TEXT UNIQUE NOT NULL,
+                minimum_length INTEGER DEFAULT 8,
+                include_numbers BOOLEAN DEFAULT false,
+                include_special BOOLEAN DEFAULT false,
+                include_ucase BOOLEAN DEFAULT false,
+                use_words BOOLEAN DEFAULT true,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            []
+            ).unwrap();
+    }
+
+    #[test]
+    fn migrations_are_numbered_in_order() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, index + 1);
+        }
+        assert_eq!(LATEST_VERSION, MIGRATIONS.len() as u32);
+    }
+    #[test]
+    fn new_database_is_migrated_once() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert_eq!(pending(&conn).unwrap().len(), MIGRATIONS.len());
+        assert_eq!(migrate(&conn).unwrap().len(), MIGRATIONS.len());
+        assert_eq!(schema_version(&conn).unwrap(), LATEST_VERSION);
+        assert!(pending(&conn).unwrap().is_empty());
+        assert!(migrate(&conn).unwrap().is_empty());
+        let user = GenerationData::new(Some(12), Some(1), None, None, None, Some(String::from("work")));
+        insert_user_profile(&conn, &user).unwrap();
+        assert_eq!(retrieve_profile_settings(&conn, &String::from("work")).unwrap(), user);
+    }
+    #[test]
+    fn first_release_profiles_are_upgraded() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_first_release_table(&conn);
+        conn.execute(
+            "INSERT INTO password_settings (profile_name, minimum_length, include_numbers, include_special, include_ucase, use_words)
+            VALUES ('old', 14, true, false, true, false);",
+            []
+            ).unwrap();
+        migrate(&conn).unwrap();
+        let upgraded = retrieve_profile_settings(&conn, &String::from("old")).unwrap();
+        let expected = GenerationData::new(Some(14), Some(1), Some(0), Some(1), Some(false), Some(String::from("old")));
+        assert_eq!(upgraded, expected);
+        let statement = conn.prepare("SELECT * FROM password_settings;").unwrap();
+        for column in SettingColumn::ALL {
+            assert!(statement.column_names().contains(&column.as_str()), "{}", column.as_str());
+        }
+    }
+    #[test]
+    fn unversioned_tables_are_matched_to_their_step() {
+        let conn = Connection::open_in_memory().unwrap();
+        // as created by the release that replaced the include_* flags with class counts
+        conn.execute(
+            "CREATE TABLE password_settings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                profile_name TEXT UNIQUE NOT NULL,
+                minimum_length INTEGER DEFAULT 8,
+                min_numbers INTEGER DEFAULT 0,
+                max_numbers INTEGER,
+                min_special INTEGER DEFAULT 0,
+                max_special INTEGER,
+                min_ucase INTEGER DEFAULT 0,
+                max_ucase INTEGER,
+                use_words BOOLEAN DEFAULT true,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            []
+            ).unwrap();
+        conn.execute("INSERT INTO password_settings (profile_name, min_numbers, max_numbers) VALUES ('counts', 2, 4);", []).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 3);
+        assert_eq!(migrate(&conn).unwrap().len(), MIGRATIONS.len() - 3);
+        let upgraded = retrieve_profile_settings(&conn, &String::from("counts")).unwrap();
+        assert_eq!((upgraded.min_numbers, upgraded.max_numbers), (2, Some(4)));
+
+        // a table with every column is up to date and only gets its version recorded
+        let current = Connection::open_in_memory().unwrap();
+        migrate(&current).unwrap();
+        current.pragma_update(None, "user_version", 0).unwrap();
+        assert_eq!(schema_version(&current).unwrap(), LATEST_VERSION);
+        assert!(migrate(&current).unwrap().is_empty());
+        let recorded: u32 = current.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(recorded, LATEST_VERSION);
+    }
+    #[test]
+    fn newer_schema_is_rejected() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", LATEST_VERSION + 1).unwrap();
+        assert!(matches!(migrate(&conn), Err(Error::Migration(_))));
+    }
+    #[test]
+    fn backup_copies_the_database_before_migrating() {
+        let directory = std::env::temp_dir().join(format!("password_generator_backup_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("profiles.db");
+        let conn = Connection::open(&path).unwrap();
+        // a new, empty database has nothing worth keeping
+        assert_eq!(backup(&conn).unwrap(), None);
+        create_first_release_table(&conn);
+        conn.execute("INSERT INTO password_settings (profile_name) VALUES ('old');", []).unwrap();
+        let first = backup(&conn).unwrap().unwrap();
+        assert_eq!(first, directory.join("profiles.db.v1.bak"));
+        let second = backup(&conn).unwrap().unwrap();
+        assert_eq!(second, directory.join("profiles.db.v1.1.bak"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&first).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        let copy = Connection::open(&first).unwrap();
+        assert_eq!(schema_version(&copy).unwrap(), 1);
+        assert!(table_columns(&copy).unwrap().contains("include_numbers"));
+        let names: u32 = copy.query_row("SELECT COUNT(*) FROM password_settings;", [], |row| row.get(0)).unwrap();
+        assert_eq!(names, 1);
+        migrate(&conn).unwrap();
+        assert_eq!(backup(&conn).unwrap(), None);
+        assert_eq!(backup(&Connection::open_in_memory().unwrap()).unwrap(), None);
+        drop(copy);
+        drop(conn);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+//! Formatting of generated passwords and profile settings for the terminal and for other
+//! programs. Results are the only thing written to stdout; status messages go to stderr, so
+//! scripts can read stdout as is.
+use clap::ValueEnum;
+use password_generator::{Error, GenerationData};
+use password_generator::database::SettingColumn;
+use password_generator::generation_logic;
+use password_generator::pattern::Pattern;
+use password_generator::pin;
+use rusqlite::types::Value as SqlValue;
+use serde_json::{json, Map, Value};
+
+/// How a batch of passwords is written to stdout
+#[derive(Debug, PartialEq, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// One password per line
+    Plain,
+    /// A `password,entropy_bits` header followed by one row per password
+    Csv,
+    /// A single object with the password, its entropy, the settings used and the profile name
+    Json,
+}
+/// Prints a status message on stderr unless `quiet` is set
+pub fn status(quiet: bool, message: &str) {
+    if !quiet {
+        eprintln!("{}", message);
+    }
+}
+/// The settings that shape a password, keyed like the columns of the profile database. Settings
+/// too large for a database column are an `Error::Parse` naming the setting.
+pub fn settings_json(settings: &GenerationData) -> Result<Value, Error> {
+    let mut object = Map::new();
+    for column in SettingColumn::ALL {
+        let value = column.value(settings)
+            .map_err(|x| Error::Parse(format!("the '{}' setting can't be stored: {}", column.as_str(), x)))?;
+        let value = match value {
+            SqlValue::Integer(flag) if column.is_flag() => json!(flag != 0),
+            SqlValue::Integer(number) => json!(number),
+            SqlValue::Real(number) => json!(number),
+            SqlValue::Text(text) => json!(text),
+            SqlValue::Null | SqlValue::Blob(_) => Value::Null,
+        };
+        object.insert(String::from(column.as_str()), value);
+    }
+    Ok(Value::Object(object))
+}
+/// Reads settings written by `settings_json`, as found in files written by the `export` command.
+/// Missing keys keep their defaults; unknown keys and values of the wrong type are errors that
+/// name the key.
+pub fn settings_from_json(value: &Value) -> Result<GenerationData, String> {
+    let object = value.as_object().ok_or_else(|| String::from("settings must be a JSON object"))?;
+    let mut settings = GenerationData::new(None, None, None, None, None, None);
+    for (key, value) in object {
+        let invalid = || format!("invalid value {} for '{}'", value, key);
+        let column = SettingColumn::from_name(key).ok_or_else(|| format!("unknown setting '{}'", key))?;
+        let sql_value = match value {
+            Value::Null => SqlValue::Null,
+            Value::Bool(flag) if column.is_flag() => SqlValue::Integer(i64::from(*flag)),
+            Value::Number(number) if !column.is_flag() => match number.as_i64() {
+                Some(number) => SqlValue::Integer(number),
+                None => SqlValue::Real(number.as_f64().ok_or_else(invalid)?),
+            },
+            Value::String(text) => SqlValue::Text(text.clone()),
+            _ => return Err(invalid()),
+        };
+        column.set_value(&mut settings, &sql_value).map_err(|_| invalid())?;
+        match column {
+            SettingColumn::MinEntropy => {
+                if let Some(bits) = settings.min_entropy {
+                    generation_logic::check_min_entropy(bits).map_err(|_| invalid())?;
+                }
+            }
+            SettingColumn::PinBlocklist => {
+                pin::parse_blocklist(settings.pin_blocklist.as_deref().unwrap_or("")).map_err(|_| invalid())?;
+            }
+            SettingColumn::Pattern => {
+                if let Some(pattern) = &settings.pattern {
+                    Pattern::parse(pattern).map_err(|_| invalid())?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(settings)
+}
+/// Quotes a CSV field as described in RFC 4180 when it holds a comma, a quote or a line break.
+/// Leading and trailing spaces are quoted too, so spreadsheet programs don't trim them.
+fn csv_field(field: &str) -> String {
+    let needs_quotes = field.contains([',', '"', '\r', '\n']) || field.starts_with(' ') || field.ends_with(' ');
+    if needs_quotes {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }
+    else{
+        String::from(field)
+    }
+}
+/// Renders `passwords` in `format`. Every password shares the same settings, so the entropy is
+/// the same for all of them. A JSON batch has a `passwords` array where a single password has a
+/// `password` string.
+pub fn format_passwords(passwords: &[String], entropy: f64, settings: &GenerationData, format: OutputFormat) -> Result<String, Error> {
+    Ok(match format {
+        OutputFormat::Plain => passwords.join("\n"),
+        OutputFormat::Csv => {
+            let mut rows = vec![String::from("password,entropy_bits")];
+            rows.extend(passwords.iter().map(|password| format!("{},{:.1}", csv_field(password), entropy)));
+            rows.join("\r\n")
+        }
+        OutputFormat::Json => {
+            let mut object = json!({
+                "entropy_bits": entropy,
+                "settings": settings_json(settings)?,
+                "profile": settings.profile,
+            });
+            match passwords {
+                [password] => object["password"] = json!(password),
+                _ => object["passwords"] = json!(passwords),
+            }
+            object.to_string()
+        }
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        csv_field,
+        format_passwords,
+        settings_from_json,
+        settings_json,
+        OutputFormat,
+    };
+    use password_generator::{Error, GenerationData};
+    use password_generator::generation_logic::{Separator, WordStyle};
+    use password_generator::token::TokenFormat;
+    use serde_json::json;
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("abc123"), "abc123");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field(" padded"), "\" padded\"");
+    }
+    #[test]
+    fn batches_are_formatted() {
+        let settings = GenerationData::new(None, None, None, None, None, None);
+        let passwords = vec![String::from("one,two"), String::from("three")];
+        assert_eq!(format_passwords(&passwords, 40.0, &settings, OutputFormat::Plain).unwrap(), "one,two\nthree");
+        assert_eq!(format_passwords(&passwords, 40.0, &settings, OutputFormat::Csv).unwrap(),
+                   "password,entropy_bits\r\n\"one,two\",40.0\r\nthree,40.0");
+        let json: serde_json::Value = serde_json::from_str(&format_passwords(&passwords, 40.0, &settings, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["passwords"][1], "three");
+        assert_eq!(json["entropy_bits"], 40.0);
+    }
+    #[test]
+    fn single_password_json_has_settings_and_profile() {
+        let mut settings = GenerationData::new(Some(14), Some(2), None, None, Some(false), Some(String::from("work")));
+        settings.symbol_set = Some(String::from("!@#"));
+        let json: serde_json::Value = serde_json::from_str(
+            &format_passwords(&[String::from("abc\"def")], 60.5, &settings, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["password"], "abc\"def");
+        assert_eq!(json["entropy_bits"], 60.5);
+        assert_eq!(json["profile"], "work");
+        assert_eq!(json["settings"]["minimum_length"], 14);
+        assert_eq!(json["settings"]["min_numbers"], 2);
+        assert_eq!(json["settings"]["symbol_set"], "!@#");
+        assert_eq!(json["settings"]["maximum_length"], serde_json::Value::Null);
+        assert_eq!(json["settings"]["separator"], "hyphen");
+        assert!(json.get("passwords").is_none());
+    }
+    #[test]
+    fn settings_round_trip_through_json() {
+        let mut settings = GenerationData::new(Some(16), Some(2), Some(1), Some(1), Some(false), None);
+        settings.max_numbers = Some(4);
+        settings.min_entropy = Some(70.5);
+        settings.separator = Separator::Space;
+        settings.word_style = WordStyle::Title;
+        settings.token_format = Some(TokenFormat::Base64Url);
+        settings.pattern = Some(String::from("Cvc-99"));
+        settings.counter = 4;
+        assert_eq!(settings_from_json(&settings_json(&settings).unwrap()), Ok(settings.clone()));
+        assert_eq!(settings_from_json(&json!({"minimum_length": "long"})), Err(String::from("invalid value \"long\" for 'minimum_length'")));
+        assert_eq!(settings_from_json(&json!({"colour": "blue"})), Err(String::from("unknown setting 'colour'")));
+        assert!(settings_from_json(&json!({"pattern": "q"})).is_err());
+        settings.maximum_length = Some(usize::MAX);
+        assert!(matches!(settings_json(&settings), Err(Error::Parse(_))));
+        assert!(matches!(format_passwords(&[String::from("abc")], 40.0, &settings, OutputFormat::Json), Err(Error::Parse(_))));
+        assert!(settings_from_json(&json!({"min_entropy": 1e300})).is_err());
+    }
+}
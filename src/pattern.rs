@@ -0,0 +1,251 @@
+//! Pattern-based generation. A pattern spells out the shape of the password one token at a time,
+//! for example `Cvccvc-99-Cvccvc` or `XXXX-XXXX-XXXX`:
+//!
+//! | token | produces                                  |
+//! |-------|-------------------------------------------|
+//! | `a`   | lowercase letter                          |
+//! | `A`   | uppercase letter                          |
+//! | `c`   | lowercase consonant                       |
+//! | `C`   | uppercase consonant                       |
+//! | `v`   | lowercase vowel                           |
+//! | `V`   | uppercase vowel                           |
+//! | `x`   | lowercase letter or digit                 |
+//! | `X`   | uppercase letter or digit                 |
+//! | `9`   | digit                                     |
+//! | `#`   | symbol from the symbol set                |
+//! | `w`   | dictionary word                           |
+//! | `W`   | dictionary word with a capital first letter |
+//!
+//! `{n}` after a token repeats it `n` times, so `9{4}` is four digits; a pattern can expand to at
+//! most `MAX_PASSWORD_LENGTH` tokens. `\` makes the next character
+//! literal. Other characters that aren't letters or digits, such as `-` or `.`, are literal as they
+//! are; letters and digits that aren't tokens have to be escaped. Consonants and vowels are the
+//! ones used by pronounceable mode, and ambiguous characters are left out of every class when
+//! `exclude_ambiguous` is set.
+use rand::seq::SliceRandom;
+use rand::{CryptoRng, RngCore};
+use crate::generation_logic::{
+    generate_word,
+    is_ambiguous,
+    GenerationError,
+    MAX_PASSWORD_LENGTH,
+    PRONOUNCEABLE_CONSONANTS,
+    PRONOUNCEABLE_VOWELS,
+};
+use crate::word_list::WordList;
+
+/// A set of characters one pattern token draws from
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PatternClass {
+    Lower,
+    Upper,
+    Consonant,
+    UpperConsonant,
+    Vowel,
+    UpperVowel,
+    LowerAlphanumeric,
+    UpperAlphanumeric,
+    Digit,
+    Symbol,
+}
+impl PatternClass {
+    /// Characters the class draws from. `symbols` is only used by `PatternClass::Symbol` and is
+    /// expected to have had ambiguous characters removed already.
+    pub fn alphabet(&self, symbols: &[char], exclude_ambiguous: bool) -> Vec<char> {
+        let chars: Vec<char> = match self {
+            PatternClass::Lower => ('a'..='z').collect(),
+            PatternClass::Upper => ('A'..='Z').collect(),
+            PatternClass::Consonant => PRONOUNCEABLE_CONSONANTS.chars().collect(),
+            PatternClass::UpperConsonant => PRONOUNCEABLE_CONSONANTS.chars().map(|ch| ch.to_ascii_uppercase()).collect(),
+            PatternClass::Vowel => PRONOUNCEABLE_VOWELS.chars().collect(),
+            PatternClass::UpperVowel => PRONOUNCEABLE_VOWELS.chars().map(|ch| ch.to_ascii_uppercase()).collect(),
+            PatternClass::LowerAlphanumeric => ('a'..='z').chain('0'..='9').collect(),
+            PatternClass::UpperAlphanumeric => ('A'..='Z').chain('0'..='9').collect(),
+            PatternClass::Digit => ('0'..='9').collect(),
+            PatternClass::Symbol => return symbols.to_vec(),
+        };
+        chars.into_iter().filter(|&ch| !(exclude_ambiguous && is_ambiguous(ch))).collect()
+    }
+}
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PatternToken {
+    Class(PatternClass),
+    /// A dictionary word, optionally with its first letter capitalized
+    Word { capitalized: bool },
+    Literal(char),
+}
+/// A parsed pattern: each token together with how many times it repeats
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pattern {
+    tokens: Vec<(PatternToken, usize)>,
+}
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern, GenerationError> {
+        let mut tokens: Vec<(PatternToken, usize)> = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(ch) = chars.next() {
+            let token = match ch {
+                'a' => PatternToken::Class(PatternClass::Lower),
+                'A' => PatternToken::Class(PatternClass::Upper),
+                'c' => PatternToken::Class(PatternClass::Consonant),
+                'C' => PatternToken::Class(PatternClass::UpperConsonant),
+                'v' => PatternToken::Class(PatternClass::Vowel),
+                'V' => PatternToken::Class(PatternClass::UpperVowel),
+                'x' => PatternToken::Class(PatternClass::LowerAlphanumeric),
+                'X' => PatternToken::Class(PatternClass::UpperAlphanumeric),
+                '9' => PatternToken::Class(PatternClass::Digit),
+                '#' => PatternToken::Class(PatternClass::Symbol),
+                'w' => PatternToken::Word { capitalized: false },
+                'W' => PatternToken::Word { capitalized: true },
+                '\\' => match chars.next() {
+                    Some(escaped) => PatternToken::Literal(escaped),
+                    None => return Err(GenerationError::InvalidPattern(String::from("the pattern ends with an unfinished escape"))),
+                },
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}')
+                        .ok_or_else(|| GenerationError::InvalidPattern(format!("'{{{}' has no closing '}}'", rest)))?;
+                    let count_text = &rest[..end];
+                    chars = rest[end + 1..].chars();
+                    let count = match count_text.parse::<usize>() {
+                        Ok(count) if count > MAX_PASSWORD_LENGTH => {
+                            return Err(GenerationError::InvalidPattern(
+                                format!("the repetition count {} is above the supported maximum of {}", count, MAX_PASSWORD_LENGTH)));
+                        }
+                        Ok(count) if count > 0 => count,
+                        _ => return Err(GenerationError::InvalidPattern(format!("'{{{}}}' is not a valid repetition count", count_text))),
+                    };
+                    match tokens.last_mut() {
+                        Some((_, repeat)) if *repeat == 1 => *repeat = count,
+                        Some(_) => return Err(GenerationError::InvalidPattern(String::from("a token can only have one repetition count"))),
+                        None => return Err(GenerationError::InvalidPattern(String::from("a repetition count has to follow a token"))),
+                    }
+                    continue;
+                }
+                ch if ch.is_alphanumeric() => {
+                    return Err(GenerationError::InvalidPattern(format!("'{}' is not a pattern token; use '\\{}' for a literal", ch, ch)));
+                }
+                ch if ch.is_control() => {
+                    return Err(GenerationError::InvalidPattern(String::from("patterns can't contain control characters")));
+                }
+                ch => PatternToken::Literal(ch),
+            };
+            tokens.push((token, 1));
+        }
+        if tokens.is_empty() {
+            return Err(GenerationError::EmptyPassword);
+        }
+        let expanded: usize = tokens.iter().map(|(_, repeat)| repeat).sum();
+        if expanded > MAX_PASSWORD_LENGTH {
+            return Err(GenerationError::InvalidPattern(
+                format!("the pattern expands to {} tokens, above the supported maximum of {}", expanded, MAX_PASSWORD_LENGTH)));
+        }
+        Ok(Pattern { tokens })
+    }
+    /// Whether the pattern contains word tokens, and so needs a word list
+    pub fn uses_words(&self) -> bool {
+        self.tokens.iter().any(|(token, _)| matches!(token, PatternToken::Word { .. }))
+    }
+    /// Words of `word_list` the word tokens draw from. When ambiguous characters are excluded and
+    /// the pattern capitalizes words, words starting with `i` or `o` are dropped as well.
+    pub fn usable_words<'a>(&self, word_list: &'a WordList, exclude_ambiguous: bool) -> Vec<&'a str> {
+        let mut words = word_list.words(exclude_ambiguous);
+        let capitalizes = self.tokens.iter().any(|(token, _)| *token == PatternToken::Word { capitalized: true });
+        if exclude_ambiguous && capitalizes {
+            words.retain(|word| !word.chars().next().is_some_and(|ch| ch.to_uppercase().any(is_ambiguous)));
+        }
+        words
+    }
+    /// Fills in the pattern. `words` must not be empty if the pattern uses words.
+    pub fn generate<R: RngCore + CryptoRng>(&self, rng: &mut R, symbols: &[char], words: &[&str], exclude_ambiguous: bool) -> String {
+        let mut password = String::new();
+        for (token, repeat) in &self.tokens {
+            let alphabet = match token {
+                PatternToken::Class(class) => class.alphabet(symbols, exclude_ambiguous),
+                _ => Vec::new(),
+            };
+            for _ in 0..*repeat {
+                match token {
+                    PatternToken::Class(_) => password.push(*alphabet.choose(rng).expect("alphabet is empty")),
+                    PatternToken::Word { capitalized } => {
+                        let word = generate_word(rng, words);
+                        let mut word_chars = word.chars();
+                        if let (true, Some(first)) = (*capitalized, word_chars.next()) {
+                            password.extend(first.to_uppercase());
+                            password.push_str(word_chars.as_str());
+                        }
+                        else{
+                            password.push_str(word);
+                        }
+                    }
+                    PatternToken::Literal(ch) => password.push(*ch),
+                }
+            }
+        }
+        password
+    }
+    /// Bits of entropy of the pattern: the sum of `log2(alphabet size)` over class tokens and
+    /// `log2(word_list_size)` over word tokens. Literals add nothing.
+    pub fn entropy_bits(&self, symbols: &[char], word_list_size: usize, exclude_ambiguous: bool) -> f64 {
+        self.tokens.iter().map(|(token, repeat)| {
+            let choices = match token {
+                PatternToken::Class(class) => class.alphabet(symbols, exclude_ambiguous).len(),
+                PatternToken::Word { .. } => word_list_size,
+                PatternToken::Literal(_) => 1,
+            };
+            *repeat as f64 * (choices as f64).log2()
+        }).sum()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        Pattern,
+        PatternClass,
+        PatternToken,
+    };
+    use crate::generation_logic::GenerationError;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn pattern_is_parsed_with_repetitions_and_escapes() {
+        let pattern = Pattern::parse("C9{3}-\\a").unwrap();
+        assert_eq!(pattern.tokens, vec![
+            (PatternToken::Class(PatternClass::UpperConsonant), 1),
+            (PatternToken::Class(PatternClass::Digit), 3),
+            (PatternToken::Literal('-'), 1),
+            (PatternToken::Literal('a'), 1),
+        ]);
+    }
+    #[test]
+    fn invalid_patterns_return_error() {
+        for pattern in ["{3}", "a{0}", "a{x}", "a{2}{3}", "q", "ab\\", "a{3", "a{", "a{9223372036854775807}", "a{10000}9{10000}"] {
+            assert!(matches!(Pattern::parse(pattern), Err(GenerationError::InvalidPattern(_))), "{}", pattern);
+        }
+        assert_eq!(Pattern::parse(""), Err(GenerationError::EmptyPassword));
+    }
+    #[test]
+    fn generated_password_follows_pattern() {
+        let pattern = Pattern::parse("XXXX-XXXX-XXXX").unwrap();
+        let mut rng = ChaCha20Rng::seed_from_u64(11);
+        for _ in 0..20 {
+            let password = pattern.generate(&mut rng, &[], &[], true);
+            let groups: Vec<&str> = password.split('-').collect();
+            assert_eq!(groups.len(), 3);
+            for group in groups {
+                assert_eq!(group.len(), 4);
+                assert!(group.chars().all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit()));
+                assert!(!group.contains(['I', 'O', '0', '1']));
+            }
+        }
+        let pattern = Pattern::parse("W.w").unwrap();
+        assert_eq!(pattern.generate(&mut rng, &[], &["kayak"], false), "Kayak.kayak");
+    }
+    #[test]
+    fn pattern_entropy_sums_token_choices() {
+        let pattern = Pattern::parse("A9{2}#-w").unwrap();
+        let expected = 26f64.log2() + 2.0 * 10f64.log2() + 5f64.log2() + 1024f64.log2();
+        assert!((pattern.entropy_bits(&['!', '@', '#', '$', '%'], 1024, false) - expected).abs() < 1e-9);
+    }
+}
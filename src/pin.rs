@@ -0,0 +1,269 @@
+//! Numeric PINs with weak-PIN rejection. Digits are drawn independently and the whole PIN is
+//! redrawn when it matches one of the enabled rules or the block list, so every acceptable PIN
+//! stays equally likely.
+//!
+//! The rules are:
+//!
+//! - `repeated`: one digit throughout (`0000`)
+//! - `sequence`: digits counting up or down by one (`1234`, `8765`)
+//! - `pattern`: a shorter block repeated (`1212`, `123123`)
+//! - `date`: a year from 1900 to 2099 or a day and month (`1987`, `0101`, `3112`) for 4 digits, and
+//!   day, month and two- or four-digit year in any common order for 6 and 8 digits
+//! - `keypad`: a straight line on a phone keypad (`2580`, `159`, `0852`)
+use std::collections::HashSet;
+use rand::{CryptoRng, RngCore};
+use rand::seq::SliceRandom;
+use crate::generation_logic::{is_ambiguous, GenerationError};
+
+/// Which weak-PIN rules are enforced
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PinRules {
+    pub repeated: bool,
+    pub sequence: bool,
+    pub pattern: bool,
+    pub date: bool,
+    pub keypad: bool,
+}
+impl Default for PinRules {
+    fn default() -> PinRules {
+        PinRules::all()
+    }
+}
+impl PinRules {
+    pub fn all() -> PinRules {
+        PinRules { repeated: true, sequence: true, pattern: true, date: true, keypad: true }
+    }
+    pub fn none() -> PinRules {
+        PinRules { repeated: false, sequence: false, pattern: false, date: false, keypad: false }
+    }
+    /// Comma-separated names of the enabled rules, or `none`
+    pub fn as_names(&self) -> String {
+        let names: Vec<&str> = [
+            (self.repeated, "repeated"),
+            (self.sequence, "sequence"),
+            (self.pattern, "pattern"),
+            (self.date, "date"),
+            (self.keypad, "keypad"),
+        ].iter().filter(|(enabled, _)| *enabled).map(|(_, name)| *name).collect();
+        if names.is_empty() {String::from("none")} else {names.join(",")}
+    }
+    /// Parses a comma-separated list of rule names; `all` and `none` are also accepted
+    pub fn from_names(names: &str) -> Option<PinRules> {
+        match names {
+            "all" => return Some(PinRules::all()),
+            "none" => return Some(PinRules::none()),
+            _ => {}
+        }
+        let mut rules = PinRules::none();
+        for name in names.split(',').map(str::trim) {
+            match name {
+                "repeated" => rules.repeated = true,
+                "sequence" => rules.sequence = true,
+                "pattern" => rules.pattern = true,
+                "date" => rules.date = true,
+                "keypad" => rules.keypad = true,
+                _ => return None,
+            }
+        }
+        Some(rules)
+    }
+}
+/// Digits PINs are drawn from; `0` and `1` are left out when ambiguous characters are excluded
+pub fn pin_digits(exclude_ambiguous: bool) -> Vec<char> {
+    ('0'..='9').filter(|&ch| !(exclude_ambiguous && is_ambiguous(ch))).collect()
+}
+/// Splits a comma-separated list of PINs to reject, checking that each one is all digits
+pub fn parse_blocklist(blocklist: &str) -> Result<HashSet<String>, GenerationError> {
+    let mut pins = HashSet::new();
+    for pin in blocklist.split(',').map(str::trim).filter(|pin| !pin.is_empty()) {
+        if !pin.chars().all(|ch| ch.is_ascii_digit()) {
+            return Err(GenerationError::InvalidPin(format!("'{}' in the block list is not a PIN", pin)));
+        }
+        pins.insert(String::from(pin));
+    }
+    Ok(pins)
+}
+/// Name of the first enabled rule `pin` breaks, if any
+pub fn weak_pin_reason(pin: &str, rules: &PinRules) -> Option<&'static str> {
+    let digits: Vec<i32> = pin.chars().filter_map(|ch| ch.to_digit(10)).map(|digit| digit as i32).collect();
+    if rules.repeated && digits.len() >= 2 && digits.iter().all(|&digit| digit == digits[0]) {
+        return Some("repeated");
+    }
+    if rules.sequence && digits.len() >= 3 {
+        let step = digits[1] - digits[0];
+        if step.abs() == 1 && digits.windows(2).all(|pair| pair[1] - pair[0] == step) {
+            return Some("sequence");
+        }
+    }
+    if rules.pattern && is_repeated_block(&digits) {
+        return Some("pattern");
+    }
+    if rules.date && is_date(&digits) {
+        return Some("date");
+    }
+    if rules.keypad && is_keypad_line(&digits) {
+        return Some("keypad");
+    }
+    None
+}
+fn is_repeated_block(digits: &[i32]) -> bool {
+    (1..digits.len()).any(|block_length| {
+        digits.len().is_multiple_of(block_length) && digits.chunks(block_length).all(|block| block == &digits[..block_length])
+    })
+}
+fn is_date(digits: &[i32]) -> bool {
+    let number = |range: std::ops::Range<usize>| digits[range].iter().fold(0, |total, &digit| total * 10 + digit);
+    let is_day_month = |day: i32, month: i32| {
+        let days_in_month = match month {
+            2 => 29,
+            4 | 6 | 9 | 11 => 30,
+            1..=12 => 31,
+            _ => 0,
+        };
+        (1..=days_in_month).contains(&day)
+    };
+    let is_year = |year: i32| (1900..=2099).contains(&year);
+    match digits.len() {
+        4 => is_year(number(0..4)) || is_day_month(number(0..2), number(2..4)) || is_day_month(number(2..4), number(0..2)),
+        // two-digit years can be anything, so only the day and month are checked
+        6 => is_day_month(number(0..2), number(2..4))
+            || is_day_month(number(2..4), number(0..2))
+            || is_day_month(number(4..6), number(2..4)),
+        8 => (is_day_month(number(0..2), number(2..4)) && is_year(number(4..8)))
+            || (is_day_month(number(2..4), number(0..2)) && is_year(number(4..8)))
+            || (is_year(number(0..4)) && is_day_month(number(6..8), number(4..6))),
+        _ => false,
+    }
+}
+/// Row and column of a digit on a phone keypad, with `0` below `8`
+fn keypad_position(digit: i32) -> (i32, i32) {
+    match digit {
+        0 => (3, 1),
+        digit => ((digit - 1) / 3, (digit - 1) % 3),
+    }
+}
+fn is_keypad_line(digits: &[i32]) -> bool {
+    if digits.len() < 3 {
+        return false;
+    }
+    let positions: Vec<(i32, i32)> = digits.iter().map(|&digit| keypad_position(digit)).collect();
+    let step = (positions[1].0 - positions[0].0, positions[1].1 - positions[0].1);
+    let is_neighbour_step = step != (0, 0) && step.0.abs() <= 1 && step.1.abs() <= 1;
+    is_neighbour_step && positions.windows(2).all(|pair| (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1) == step)
+}
+/// Redraws allowed before `generate_pin` decides the rules reject (nearly) every PIN
+const MAX_PIN_ATTEMPTS: usize = 10_000;
+/// Draws PINs of `length` digits from `digits` until one passes the rules and the block list
+pub fn generate_pin<R: RngCore + CryptoRng>(rng: &mut R, length: usize, digits: &[char], rules: &PinRules, blocklist: &HashSet<String>) -> Result<String, GenerationError> {
+    if length == 0 {
+        return Err(GenerationError::EmptyPassword);
+    }
+    for _ in 0..MAX_PIN_ATTEMPTS {
+        let pin: String = (0..length).map(|_| *digits.choose(rng).expect("digit alphabet is empty")).collect();
+        if weak_pin_reason(&pin, rules).is_none() && !blocklist.contains(&pin) {
+            return Ok(pin);
+        }
+    }
+    Err(GenerationError::InvalidPin(format!("the rules reject every {}-digit PIN", length)))
+}
+/// Longest PIN for which `pin_entropy` counts the acceptable PINs one by one
+const MAX_COUNTED_PIN_LENGTH: usize = 6;
+/// Bits of entropy of a PIN, `log2(number of acceptable PINs)`. Up to 6 digits the acceptable PINs
+/// are counted exactly; longer PINs are taken as `length * log2(digits)` since the rules reject
+/// only a tiny share of them.
+pub fn pin_entropy(length: usize, digits: &[char], rules: &PinRules, blocklist: &HashSet<String>) -> Result<f64, GenerationError> {
+    if length == 0 {
+        return Err(GenerationError::EmptyPassword);
+    }
+    if length > MAX_COUNTED_PIN_LENGTH {
+        return Ok(length as f64 * (digits.len() as f64).log2());
+    }
+    let total = digits.len().pow(length as u32);
+    let mut acceptable = 0;
+    let mut pin = String::with_capacity(length);
+    for mut idx in 0..total {
+        pin.clear();
+        for _ in 0..length {
+            pin.push(digits[idx % digits.len()]);
+            idx /= digits.len();
+        }
+        if weak_pin_reason(&pin, rules).is_none() && !blocklist.contains(&pin) {
+            acceptable += 1;
+        }
+    }
+    if acceptable == 0 {
+        return Err(GenerationError::InvalidPin(format!("the rules reject every {}-digit PIN", length)));
+    }
+    Ok((acceptable as f64).log2())
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        generate_pin,
+        parse_blocklist,
+        pin_entropy,
+        weak_pin_reason,
+        PinRules,
+    };
+    use std::collections::HashSet;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn weak_pins_are_recognized() {
+        let rules = PinRules::all();
+        let cases = [
+            ("0000", Some("repeated")),
+            ("1234", Some("sequence")),
+            ("9876", Some("sequence")),
+            ("1212", Some("pattern")),
+            ("123123", Some("pattern")),
+            ("1987", Some("date")),
+            ("0105", Some("date")),
+            ("3112", Some("date")),
+            ("25121990", Some("date")),
+            ("2580", Some("keypad")),
+            ("0852", Some("keypad")),
+            ("159", Some("keypad")),
+            ("3579", None),
+            ("8346", None),
+            ("6093", None),
+        ];
+        for (pin, reason) in cases {
+            assert_eq!(weak_pin_reason(pin, &rules), reason, "{}", pin);
+        }
+        assert_eq!(weak_pin_reason("1234", &PinRules::none()), None);
+    }
+    #[test]
+    fn rule_names_round_trip() {
+        let rules = PinRules::from_names("sequence,keypad").unwrap();
+        assert_eq!(rules, PinRules { repeated: false, sequence: true, pattern: false, date: false, keypad: true });
+        assert_eq!(PinRules::from_names(&rules.as_names()), Some(rules));
+        assert_eq!(PinRules::from_names(&PinRules::none().as_names()), Some(PinRules::none()));
+        assert_eq!(PinRules::from_names("sequence,birthday"), None);
+    }
+    #[test]
+    fn generated_pins_pass_the_rules() {
+        let digits: Vec<char> = ('0'..='9').collect();
+        let blocklist = parse_blocklist("3579, 8346").unwrap();
+        let mut rng = ChaCha20Rng::seed_from_u64(12);
+        for _ in 0..500 {
+            let pin = generate_pin(&mut rng, 4, &digits, &PinRules::all(), &blocklist).unwrap();
+            assert_eq!(pin.len(), 4);
+            assert_eq!(weak_pin_reason(&pin, &PinRules::all()), None);
+            assert!(!blocklist.contains(&pin));
+        }
+        assert!(parse_blocklist("12a4").is_err());
+    }
+    #[test]
+    fn pin_entropy_counts_acceptable_pins() {
+        let digits: Vec<char> = ('0'..='9').collect();
+        assert!((pin_entropy(4, &digits, &PinRules::none(), &HashSet::new()).unwrap() - 10000f64.log2()).abs() < 1e-9);
+        let blocklist = parse_blocklist("3579,8346").unwrap();
+        assert!((pin_entropy(4, &digits, &PinRules::none(), &blocklist).unwrap() - 9998f64.log2()).abs() < 1e-9);
+        assert!(pin_entropy(4, &digits, &PinRules::all(), &blocklist).unwrap() < 9998f64.log2());
+        // every 1-digit PIN is blocked
+        let everything = parse_blocklist("0,1,2,3,4,5,6,7,8,9").unwrap();
+        assert!(pin_entropy(1, &digits, &PinRules::none(), &everything).is_err());
+    }
+}
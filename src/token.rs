@@ -0,0 +1,169 @@
+//! Secrets that aren't meant to be typed by people, such as API keys and signing keys: raw bytes
+//! from the rng, encoded as text.
+//!
+//! A token is `<prefix><encoded bytes>`, optionally followed by `_` and a checksum: the first four
+//! bytes of the SHA-256 hash of everything before the underscore, in hex. The checksum lets a
+//! secret scanner or an API reject mistyped or truncated tokens without a lookup.
+use data_encoding::{BASE32, BASE32_NOPAD, BASE64URL_NOPAD, HEXLOWER};
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use crate::generation_logic::GenerationError;
+
+/// Bytes used when a token format is chosen without a byte count
+pub const DEFAULT_TOKEN_BYTES: usize = 32;
+/// Most random bytes a token may use, far more than any key needs
+pub const MAX_TOKEN_BYTES: usize = 1024;
+/// How the random bytes of a token are written out
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenFormat {
+    /// Lowercase hexadecimal
+    Hex,
+    /// RFC 4648 base32 with `=` padding
+    Base32,
+    /// RFC 4648 base32 without padding
+    Base32NoPad,
+    /// RFC 4648 URL-safe base64 without padding
+    Base64Url,
+    /// A random (version 4) UUID, which always uses 16 bytes, 122 of them random
+    Uuid,
+}
+impl TokenFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenFormat::Hex => "hex",
+            TokenFormat::Base32 => "base32",
+            TokenFormat::Base32NoPad => "base32_nopad",
+            TokenFormat::Base64Url => "base64url",
+            TokenFormat::Uuid => "uuid",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<TokenFormat> {
+        match name {
+            "hex" => Some(TokenFormat::Hex),
+            "base32" => Some(TokenFormat::Base32),
+            "base32_nopad" => Some(TokenFormat::Base32NoPad),
+            "base64url" => Some(TokenFormat::Base64Url),
+            "uuid" => Some(TokenFormat::Uuid),
+            _ => None,
+        }
+    }
+    /// Bits of entropy in a token of `byte_count` random bytes
+    pub fn entropy_bits(&self, byte_count: usize) -> f64 {
+        match self {
+            TokenFormat::Uuid => 122.0,
+            _ => byte_count as f64 * 8.0,
+        }
+    }
+    fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            TokenFormat::Hex => HEXLOWER.encode(bytes),
+            TokenFormat::Base32 => BASE32.encode(bytes),
+            TokenFormat::Base32NoPad => BASE32_NOPAD.encode(bytes),
+            TokenFormat::Base64Url => BASE64URL_NOPAD.encode(bytes),
+            TokenFormat::Uuid => {
+                let hex = HEXLOWER.encode(bytes);
+                format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+            }
+        }
+    }
+}
+/// Draws `byte_count` bytes from `rng` (16 for UUIDs) and encodes them in `format`. Returns
+/// `GenerationError::InvalidToken` if `byte_count` is above `MAX_TOKEN_BYTES`.
+pub fn generate_token<R: RngCore + CryptoRng>(rng: &mut R, format: TokenFormat, byte_count: usize, prefix: &str, checksum: bool) -> Result<String, GenerationError> {
+    let byte_count = if format == TokenFormat::Uuid {16} else {byte_count};
+    if byte_count > MAX_TOKEN_BYTES {
+        return Err(too_many_bytes(byte_count));
+    }
+    let mut bytes = vec![0u8; byte_count];
+    rng.fill_bytes(&mut bytes);
+    if format == TokenFormat::Uuid {
+        // version 4 in the high nibble of byte 6, RFC 4122 variant in the top bits of byte 8
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    }
+    let token = format!("{}{}", prefix, format.encode(&bytes));
+    if checksum {
+        let checksum = token_checksum(&token);
+        Ok(format!("{}_{}", token, checksum))
+    }
+    else{
+        Ok(token)
+    }
+}
+pub(crate) fn too_many_bytes(byte_count: usize) -> GenerationError {
+    GenerationError::InvalidToken(format!("{} bytes is above the supported maximum of {}", byte_count, MAX_TOKEN_BYTES))
+}
+fn token_checksum(token: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(token.as_bytes())[..4])
+}
+/// Checks the checksum at the end of a token made with `checksum` enabled
+pub fn verify_token_checksum(token: &str) -> bool {
+    match token.rsplit_once('_') {
+        Some((body, checksum)) => token_checksum(body) == checksum,
+        None => false,
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        generate_token,
+        verify_token_checksum,
+        TokenFormat,
+        MAX_TOKEN_BYTES,
+    };
+    use crate::generation_logic::GenerationError;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn tokens_have_expected_lengths_and_alphabets() {
+        let mut rng = ChaCha20Rng::seed_from_u64(13);
+        let hex = generate_token(&mut rng, TokenFormat::Hex, 32, "", false).unwrap();
+        assert_eq!(hex.len(), 64);
+        assert!(hex.chars().all(|ch| ch.is_ascii_hexdigit() && !ch.is_ascii_uppercase()));
+        let base32 = generate_token(&mut rng, TokenFormat::Base32, 16, "", false).unwrap();
+        assert_eq!(base32.len(), 32);
+        assert!(base32.ends_with("======"));
+        let base32_nopad = generate_token(&mut rng, TokenFormat::Base32NoPad, 16, "", false).unwrap();
+        assert_eq!(base32_nopad.len(), 26);
+        assert!(base32_nopad.chars().all(|ch| ch.is_ascii_uppercase() || ('2'..='7').contains(&ch)));
+        let base64url = generate_token(&mut rng, TokenFormat::Base64Url, 32, "", false).unwrap();
+        assert_eq!(base64url.len(), 43);
+        assert!(base64url.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_'));
+    }
+    #[test]
+    fn uuid_has_version_and_variant() {
+        let mut rng = ChaCha20Rng::seed_from_u64(14);
+        for _ in 0..20 {
+            let uuid = generate_token(&mut rng, TokenFormat::Uuid, 32, "", false).unwrap();
+            let groups: Vec<&str> = uuid.split('-').collect();
+            assert_eq!(groups.iter().map(|group| group.len()).collect::<Vec<usize>>(), vec![8, 4, 4, 4, 12]);
+            assert!(groups[2].starts_with('4'));
+            assert!(groups[3].starts_with(['8', '9', 'a', 'b']));
+        }
+    }
+    #[test]
+    fn prefix_and_checksum_are_added() {
+        let mut rng = ChaCha20Rng::seed_from_u64(15);
+        let token = generate_token(&mut rng, TokenFormat::Hex, 16, "sk_live_", true).unwrap();
+        assert!(token.starts_with("sk_live_"));
+        assert_eq!(token.len(), "sk_live_".len() + 32 + 9);
+        assert!(verify_token_checksum(&token));
+        let mut tampered = token.clone();
+        tampered.replace_range(8..9, if &token[8..9] == "0" {"1"} else {"0"});
+        assert!(!verify_token_checksum(&tampered));
+    }
+    #[test]
+    fn too_many_bytes_return_error() {
+        let mut rng = ChaCha20Rng::seed_from_u64(17);
+        assert_eq!(generate_token(&mut rng, TokenFormat::Hex, MAX_TOKEN_BYTES, "", false).unwrap().len(), 2 * MAX_TOKEN_BYTES);
+        assert!(matches!(generate_token(&mut rng, TokenFormat::Hex, usize::MAX, "", false), Err(GenerationError::InvalidToken(_))));
+    }
+    #[test]
+    fn seeded_rng_gives_known_token() {
+        let mut rng = ChaCha20Rng::seed_from_u64(16);
+        let first = generate_token(&mut rng, TokenFormat::Base64Url, 24, "whsec_", false).unwrap();
+        let mut rng = ChaCha20Rng::seed_from_u64(16);
+        assert_eq!(generate_token(&mut rng, TokenFormat::Base64Url, 24, "whsec_", false).unwrap(), first);
+    }
+}
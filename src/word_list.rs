@@ -0,0 +1,216 @@
+//! Word lists for dictionary and passphrase modes. Either one of the lists bundled with the
+//! `random_word` crate, or a local file such as one of the EFF diceware lists.
+//!
+//! The English list is always built in; German, Spanish, French and Chinese are behind the crate
+//! features `de`, `es`, `fr` and `zh`. Entries of the bundled lists that aren't a single run of
+//! letters (abbreviations with digits, hyphenated phrases) are skipped, and the Chinese list's
+//! traditional and simplified forms become separate words.
+//!
+//! Word list files hold one word per line. Lines may start with a dice roll (`11111 abacus`,
+//! as in the EFF lists), `#` starts a comment, and blank lines are ignored. Words are lowercased
+//! since uppercase characters are added separately by the generator. Loaded files are
+//! validated, de-duplicated and cached for the rest of the process; the cache entry is refreshed
+//! when the file's modification time changes.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use sha2::{Digest, Sha256};
+use crate::generation_logic::{
+    is_ambiguous,
+    GenerationError,
+    Language,
+};
+
+pub enum WordList {
+    Builtin(&'static [&'static str]),
+    Loaded(Arc<LoadedWordList>),
+}
+/// The contents of a word list file along with the SHA-256 hash of the raw file
+pub struct LoadedWordList {
+    pub words: Vec<String>,
+    pub content_hash: String,
+}
+struct CacheEntry {
+    modified: Option<SystemTime>,
+    list: Arc<LoadedWordList>,
+}
+fn cache() -> &'static Mutex<HashMap<String, CacheEntry>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, CacheEntry>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+/// The `random_word` language for `language`, or `None` if its feature isn't enabled
+fn random_word_lang(language: Language) -> Option<random_word::Lang> {
+    match language {
+        Language::English => Some(random_word::Lang::En),
+        #[cfg(feature = "de")]
+        Language::German => Some(random_word::Lang::De),
+        #[cfg(not(feature = "de"))]
+        Language::German => None,
+        #[cfg(feature = "es")]
+        Language::Spanish => Some(random_word::Lang::Es),
+        #[cfg(not(feature = "es"))]
+        Language::Spanish => None,
+        #[cfg(feature = "fr")]
+        Language::French => Some(random_word::Lang::Fr),
+        #[cfg(not(feature = "fr"))]
+        Language::French => None,
+        #[cfg(feature = "zh")]
+        Language::Chinese => Some(random_word::Lang::Zh),
+        #[cfg(not(feature = "zh"))]
+        Language::Chinese => None,
+    }
+}
+impl WordList {
+    /// The list bundled for `language`, filtered once per process
+    pub fn builtin(language: Language) -> Result<WordList, GenerationError> {
+        static BUILTIN: [OnceLock<Vec<&'static str>>; 5] = [const { OnceLock::new() }; 5];
+        let lang = random_word_lang(language).ok_or(GenerationError::UnsupportedLanguage(language))?;
+        let words = BUILTIN[language as usize].get_or_init(|| {
+            let mut seen = HashSet::new();
+            random_word::all(lang).iter()
+                .flat_map(|entry| entry.split_whitespace())
+                .filter(|word| word.chars().all(char::is_alphabetic))
+                .filter(|word| seen.insert(*word))
+                .collect()
+        });
+        Ok(WordList::Builtin(words))
+    }
+    /// Reads a word list file, or returns the cached copy if the file hasn't been modified since
+    /// it was last read
+    pub fn load(path: &str) -> Result<WordList, GenerationError> {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let mut cache = cache().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(entry) = cache.get(path) {
+            if entry.modified.is_some() && entry.modified == modified {
+                return Ok(WordList::Loaded(Arc::clone(&entry.list)));
+            }
+        }
+        let contents = fs::read(path)
+            .map_err(|x| GenerationError::InvalidWordList(format!("could not read '{}': {}", path, x)))?;
+        let list = Arc::new(parse_word_list(path, &contents)?);
+        cache.insert(String::from(path), CacheEntry { modified, list: Arc::clone(&list) });
+        Ok(WordList::Loaded(list))
+    }
+    /// Same as `load`, but fails if the file's hash no longer matches `expected_hash`
+    pub fn load_verified(path: &str, expected_hash: &str) -> Result<WordList, GenerationError> {
+        let word_list = WordList::load(path)?;
+        match word_list.content_hash() {
+            Some(content_hash) if content_hash != expected_hash => {
+                Err(GenerationError::InvalidWordList(
+                    format!("'{}' has changed since it was saved to the profile (expected hash {}, found {})",
+                            path, expected_hash, content_hash)))
+            }
+            _ => Ok(word_list),
+        }
+    }
+    /// SHA-256 hash of the file this list was loaded from, in hex
+    pub fn content_hash(&self) -> Option<&str> {
+        match self {
+            WordList::Builtin(_) => None,
+            WordList::Loaded(list) => Some(&list.content_hash),
+        }
+    }
+    /// The words in the list, leaving out those with ambiguous characters if requested
+    pub fn words(&self, exclude_ambiguous: bool) -> Vec<&str> {
+        let acceptable = |word: &&str| !(exclude_ambiguous && word.chars().any(is_ambiguous));
+        match self {
+            WordList::Builtin(words) => words.iter().copied().filter(acceptable).collect(),
+            WordList::Loaded(list) => list.words.iter().map(String::as_str).filter(acceptable).collect(),
+        }
+    }
+}
+fn parse_word_list(path: &str, contents: &[u8]) -> Result<LoadedWordList, GenerationError> {
+    let text = std::str::from_utf8(contents)
+        .map_err(|_| GenerationError::InvalidWordList(format!("'{}' is not valid UTF-8", path)))?;
+    let mut words = Vec::new();
+    let mut seen = HashSet::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let word = parse_word_list_line(line).map_err(|reason| {
+            GenerationError::InvalidWordList(format!("{} line {}: {}", path, line_idx + 1, reason))
+        })?;
+        if let Some(word) = word {
+            if seen.insert(word.clone()) {
+                words.push(word);
+            }
+        }
+    }
+    if words.is_empty() {
+        return Err(GenerationError::InvalidWordList(format!("'{}' contains no words", path)));
+    }
+    let content_hash = Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(LoadedWordList { words, content_hash })
+}
+/// Extracts the word from one line of a word list file, dropping comments and a leading dice roll.
+/// Returns `None` for lines with no word on them.
+fn parse_word_list_line(line: &str) -> Result<Option<String>, String> {
+    let line = match line.find('#') {
+        Some(comment_start) => &line[..comment_start],
+        None => line,
+    };
+    let mut tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() == 2 && tokens[0].chars().all(|ch| ('1'..='6').contains(&ch)) {
+        tokens.remove(0);
+    }
+    match tokens.as_slice() {
+        [] => Ok(None),
+        [word] if word.chars().any(char::is_control) => Err(String::from("words can't contain control characters")),
+        [word] => Ok(Some(word.to_lowercase())),
+        _ => Err(format!("expected a single word but found '{}'", line.trim())),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_word_list_line,
+        WordList,
+    };
+    use crate::generation_logic::GenerationError;
+
+    fn write_temp_list(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+    #[test]
+    fn parse_plain_eff_and_comment_lines() {
+        assert_eq!(parse_word_list_line("abacus"), Ok(Some(String::from("abacus"))));
+        assert_eq!(parse_word_list_line("11111\tabacus"), Ok(Some(String::from("abacus"))));
+        assert_eq!(parse_word_list_line("  6666 zoom  # trailing comment"), Ok(Some(String::from("zoom"))));
+        assert_eq!(parse_word_list_line("# just a comment"), Ok(None));
+        assert_eq!(parse_word_list_line("   "), Ok(None));
+        assert!(parse_word_list_line("two words").is_err());
+    }
+    #[test]
+    fn load_deduplicates_and_caches() {
+        let path = write_temp_list("password_generator_test_dedup_list.txt", "# team list\nalpha\nbravo\nAlpha\nbravo\n");
+        let first = WordList::load(&path).unwrap();
+        let second = WordList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(first.words(false), vec!["alpha", "bravo"]);
+        match (first, second) {
+            (WordList::Loaded(first), WordList::Loaded(second)) => assert!(std::sync::Arc::ptr_eq(&first, &second)),
+            _ => panic!(),
+        }
+    }
+    #[test]
+    fn invalid_line_reports_line_number() {
+        let path = write_temp_list("password_generator_test_invalid_list.txt", "alpha\nbravo charlie\n");
+        let result = WordList::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(GenerationError::InvalidWordList(reason)) => assert!(reason.contains("line 2")),
+            _ => panic!(),
+        }
+    }
+    #[test]
+    fn changed_list_fails_verification() {
+        let path = write_temp_list("password_generator_test_changed_list.txt", "alpha\nbravo\n");
+        let saved_hash = String::from(WordList::load(&path).unwrap().content_hash().unwrap());
+        let unchanged = WordList::load_verified(&path, &saved_hash);
+        let changed = WordList::load_verified(&path, "0000");
+        std::fs::remove_file(&path).unwrap();
+        assert!(unchanged.is_ok());
+        assert!(matches!(changed, Err(GenerationError::InvalidWordList(_))));
+    }
+}